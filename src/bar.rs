@@ -0,0 +1,54 @@
+use crate::{Environment, IconMode, block::create_blocks, json::Value, render};
+use std::{io::Write as _, path::PathBuf, time::Duration};
+
+/// Status line in i3bar protocol, one item per colored piece of the blocks
+pub fn status(names: &[&str], environ: &Environment, mode: IconMode) -> Value {
+    render::set(&render::Segments);
+    let mut items = vec![];
+    for name in names {
+        for mut block in create_blocks(&[name], environ) {
+            block.extend();
+            let rendered = crate::icon::display(block.as_ref(), mode).to_string();
+            let segments: Vec<_> = render::segments(&rendered)
+                .into_iter()
+                .filter(|(text, _)| !text.trim().is_empty())
+                .collect();
+            let count = segments.len();
+            for (idx, (text, color)) in segments.into_iter().enumerate() {
+                let mut item = vec![("name", (*name).into()), ("full_text", text.into())];
+                if let Some(color) = color {
+                    item.push(("color", color.into()));
+                }
+                // Pieces of one block stay together
+                if idx + 1 != count {
+                    item.push(("separator", false.into()));
+                }
+                items.push(Value::Object(item));
+            }
+        }
+    }
+    render::set(&render::Readline);
+    Value::Array(items)
+}
+
+/// Prints status lines for i3bar or swaybar, every `interval` or only once
+pub fn serve(names: &[&str], mode: IconMode, interval: Option<Duration>) {
+    let dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("/"));
+    let mut out = std::io::stdout().lock();
+    if writeln!(out, "{{\"version\":1}}\n[").is_err() {
+        return;
+    }
+    loop {
+        let environ = Environment::at(dir.clone(), dir.clone());
+        let line = status(names, &environ, mode);
+        let Some(interval) = interval else {
+            drop(writeln!(out, "{line}\n]"));
+            return;
+        };
+        // Bar has quit
+        if writeln!(out, "{line},").is_err() {
+            return;
+        }
+        std::thread::sleep(interval);
+    }
+}
@@ -1,9 +1,10 @@
-use crate::{Environment, Pretty};
+use crate::{Environment, Pretty, json::Value};
 use heck::ToPascalCase as _;
 use linkme::distributed_slice;
 use std::{collections::HashMap, sync::LazyLock};
 
 mod build_info;
+mod container;
 mod elapsed;
 mod git;
 mod hostuser;
@@ -22,6 +23,17 @@ pub trait Block: Pretty {
     where
         Self: Sized;
     fn extend(&mut self) {}
+    /// Structured data behind the block, for `run --format json`
+    fn json(&self) -> Value;
+    /// Blocks with lower priority are compacted and then dropped first when the line does not fit
+    /// into terminal. Blocks with `u8::MAX` are never dropped
+    fn priority(&self) -> u8 {
+        50
+    }
+    /// Switches to a shorter form, returns `false` if the block can not be any shorter
+    fn compact(&mut self) -> bool {
+        false
+    }
 }
 
 type Constructor = fn(&Environment) -> Option<Box<dyn Block>>;
@@ -45,6 +57,10 @@ pub(crate) use register_block;
 static BLOCK_KINDS_MAP: LazyLock<HashMap<&str, Constructor>> =
     LazyLock::new(|| BLOCK_KINDS.iter().copied().collect());
 
+pub fn is_block(name: &str) -> bool {
+    BLOCK_KINDS_MAP.contains_key(&*name.to_pascal_case())
+}
+
 pub fn create_blocks(names: &[&str], environ: &Environment) -> Vec<Box<dyn Block>> {
     names
         .iter()
@@ -1,4 +1,4 @@
-use crate::{Block, Color, Environment, IconMode, Pretty, Style, WithStyle as _, file};
+use crate::{Block, Environment, IconMode, Pretty, Role, WithStyle as _, file, json::Value};
 
 #[derive(Hash, PartialEq, Eq)]
 enum Kind {
@@ -48,7 +48,7 @@ impl Block for BuildInfo {
             ("jr", Kind::Jr),
             ("gradle.properties", Kind::Gradle),
         ] {
-            if file::points_to_file(name) {
+            if file::points_to_file(environ.cwd.join(name)) {
                 kinds.push(kind);
             }
         }
@@ -65,19 +65,29 @@ impl Block for BuildInfo {
 
         (!kinds.is_empty()).then_some(Self(kinds))
     }
+
+    fn json(&self) -> Value {
+        Value::Object(vec![(
+            "kinds",
+            self.0.iter().map(Kind::as_str).collect::<Vec<_>>().into(),
+        )])
+    }
+
+    fn priority(&self) -> u8 {
+        20
+    }
 }
 
 impl Pretty for BuildInfo {
     fn pretty(&self, f: &mut std::fmt::Formatter<'_>, _: IconMode) -> std::fmt::Result {
-        f.with_style(Color::PURPLE, Style::empty(), |f| {
-            write!(f, "[")?;
+        f.segment(Role::Build, "", |f| {
             for (idx, kind) in self.0.iter().enumerate() {
                 if idx != 0 {
                     write!(f, " ")?;
                 }
                 write!(f, "{}", kind.as_str())?;
             }
-            write!(f, "]")
+            Ok(())
         })
     }
 }
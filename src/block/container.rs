@@ -0,0 +1,137 @@
+use crate::{
+    Block, Environment, Icon, IconMode, Pretty, Role, WithStyle as _, file, json::Value, virt,
+};
+use std::{
+    fs::File,
+    io::{BufRead as _, BufReader},
+};
+
+pub struct Container {
+    /// Tool which manages the container, like podman, toolbox or distrobox
+    manager: Option<String>,
+    name: Option<String>,
+    image: Option<String>,
+    rootless: bool,
+}
+
+super::register_block!(Container);
+
+impl Block for Container {
+    fn new(environ: &Environment) -> Option<Self> {
+        let mut container = Container {
+            manager: None,
+            name: None,
+            image: None,
+            rootless: false,
+        };
+
+        // Written by podman, and thus by toolbox and distrobox on top of it
+        if let Ok(file) = File::open(environ.sysroot.path("/run/.containerenv")) {
+            for line in BufReader::new(file).lines().map_while(Result::ok) {
+                let Some((key, value)) = line.split_once('=') else {
+                    continue;
+                };
+                let value = value.trim_matches('"');
+                if value.is_empty() {
+                    continue;
+                }
+                match key {
+                    // e.g. "podman-4.9.3"
+                    "engine" => {
+                        container.manager = value.split('-').next().map(ToOwned::to_owned);
+                    }
+                    "name" => container.name = Some(value.to_owned()),
+                    "image" => container.image = Some(value.to_owned()),
+                    "rootless" => container.rootless = value == "1",
+                    _ => {}
+                }
+            }
+        }
+
+        if file::exists(environ.sysroot.path("/run/.toolboxenv"))
+            || environ.var_os("TOOLBOX_PATH").is_some()
+        {
+            container.manager = Some("toolbox".to_owned());
+        }
+        if environ.var_os("DISTROBOX_ENTER_PATH").is_some() {
+            container.manager = Some("distrobox".to_owned());
+        }
+        if container.name.is_none() {
+            // Set by distrobox, and by toolbox in newer versions
+            container.name = environ.var("CONTAINER_ID").map(ToOwned::to_owned);
+        }
+        if container.manager.is_none() {
+            container.manager =
+                std::fs::read_to_string(environ.sysroot.path("/run/host/container-manager"))
+                    .ok()
+                    .or_else(|| virt::init_container_var(&environ.sysroot))
+                    .map(|manager| manager.trim().to_owned());
+        }
+
+        (container.manager.is_some() || container.name.is_some()).then_some(container)
+    }
+
+    fn json(&self) -> Value {
+        Value::Object(vec![
+            ("manager", self.manager.as_deref().into()),
+            ("name", self.name.as_deref().into()),
+            ("image", self.image.as_deref().into()),
+            ("rootless", self.rootless.into()),
+        ])
+    }
+
+    fn priority(&self) -> u8 {
+        70
+    }
+}
+
+impl Icon for Container {
+    fn key(&self) -> &'static str {
+        "container"
+    }
+
+    fn builtin(&self, mode: IconMode) -> &'static str {
+        use IconMode::*;
+        match mode {
+            Text => "in",
+            Icons | MinimalIcons => "",
+        }
+    }
+}
+
+impl Pretty for Container {
+    fn pretty(&self, f: &mut std::fmt::Formatter<'_>, mode: IconMode) -> std::fmt::Result {
+        let name = self
+            .name
+            .as_deref()
+            .or(self.manager.as_deref())
+            .unwrap_or_default();
+        f.segment(Role::Container, name, |f| {
+            write!(f, "{} {name}", self.icon(mode))?;
+            if let Some(image) = &self.image {
+                // Registry is rarely interesting, "fedora-toolbox:40" is
+                write!(f, "|{}", image.rsplit('/').next().unwrap_or(image))?;
+            }
+            if self.rootless {
+                write!(f, " {}", RootlessIcon.icon(mode))?;
+            }
+            Ok(())
+        })
+    }
+}
+
+struct RootlessIcon;
+
+impl Icon for RootlessIcon {
+    fn key(&self) -> &'static str {
+        "rootless"
+    }
+
+    fn builtin(&self, mode: IconMode) -> &'static str {
+        use IconMode::*;
+        match mode {
+            Text => "rootless",
+            Icons | MinimalIcons => "󰀄",
+        }
+    }
+}
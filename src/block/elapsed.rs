@@ -1,6 +1,6 @@
 use std::time::Duration;
 
-use crate::{Block, Color, Environment, Icon, IconMode, Pretty, Style, WithStyle as _};
+use crate::{Block, Environment, Icon, IconMode, Pretty, Role, WithStyle as _, json::Value};
 
 pub struct Elapsed(Duration);
 
@@ -11,10 +11,25 @@ impl Block for Elapsed {
         let elapsed = environ.elapsed_time.unwrap_or_default();
         (elapsed > Duration::from_millis(100)).then_some(Elapsed(elapsed))
     }
+
+    fn json(&self) -> Value {
+        Value::Object(vec![(
+            "milliseconds",
+            u64::try_from(self.0.as_millis()).unwrap_or(u64::MAX).into(),
+        )])
+    }
+
+    fn priority(&self) -> u8 {
+        55
+    }
 }
 
 impl Icon for Elapsed {
-    fn icon(&self, mode: IconMode) -> &'static str {
+    fn key(&self) -> &'static str {
+        "elapsed"
+    }
+
+    fn builtin(&self, mode: IconMode) -> &'static str {
         use IconMode::*;
         match &mode {
             Text => "took",
@@ -25,7 +40,7 @@ impl Icon for Elapsed {
 
 impl Pretty for Elapsed {
     fn pretty(&self, f: &mut std::fmt::Formatter<'_>, mode: IconMode) -> std::fmt::Result {
-        f.with_style(Color::CYAN, Style::empty(), |f| {
+        f.with_role(Role::Elapsed, |f| {
             write!(
                 f,
                 "({} {})",
@@ -1,4 +1,7 @@
-use crate::{Block, Color, Environment, Icon, IconMode, Pretty, Style, WithStyle as _, file};
+use crate::{
+    Block, Environment, Icon, IconMode, Pretty, Role, WithStyle as _, daemon, file, json::Value,
+    link, profile,
+};
 use anyhow::{Context as _, Result};
 use memmap2::Mmap;
 use rustix::process::Signal;
@@ -186,7 +189,16 @@ struct Head {
 }
 
 impl Icon for HeadKind {
-    fn icon(&self, mode: IconMode) -> &'static str {
+    fn key(&self) -> &'static str {
+        match self {
+            Self::Branch(_) => "git.branch",
+            Self::Unborn(_) => "git.unborn",
+            Self::Commit(_) => "git.commit",
+            Self::Unknown => "git.unknown",
+        }
+    }
+
+    fn builtin(&self, mode: IconMode) -> &'static str {
         use IconMode::*;
         match self {
             Self::Branch(_) => match mode {
@@ -258,6 +270,16 @@ impl Head {
         }
     }
 
+    fn json(&self) -> Value {
+        let (kind, name) = match &self.kind {
+            HeadKind::Branch(name) => ("branch", Some(name.as_str())),
+            HeadKind::Unborn(name) => ("unborn", Some(name.as_str())),
+            HeadKind::Commit(id) => ("commit", Some(id.as_str())),
+            HeadKind::Unknown => ("unknown", None),
+        };
+        Value::Object(vec![("kind", kind.into()), ("name", name.into())])
+    }
+
     // WHY WHY WHY send help
     fn refine_unborn(mut self) -> Self {
         if let HeadKind::Branch(_) = self.kind
@@ -318,8 +340,43 @@ impl State {
     }
 }
 
+impl State {
+    fn json(&self) -> Value {
+        match self {
+            State::Bisecting => Value::Object(vec![("kind", "bisecting".into())]),
+            State::Merging { head } => Value::Object(vec![
+                ("kind", "merging".into()),
+                ("head", head.as_str().into()),
+            ]),
+            State::CherryPicking { head } => Value::Object(vec![
+                ("kind", "cherry-picking".into()),
+                ("head", head.as_str().into()),
+            ]),
+            State::Reverting { head } => Value::Object(vec![
+                ("kind", "reverting".into()),
+                ("head", head.as_str().into()),
+            ]),
+            State::Rebasing { done, todo } => Value::Object(vec![
+                ("kind", "rebasing".into()),
+                ("done", (*done).into()),
+                ("todo", (*todo).into()),
+            ]),
+        }
+    }
+}
+
 impl Icon for State {
-    fn icon(&self, mode: IconMode) -> &'static str {
+    fn key(&self) -> &'static str {
+        match self {
+            Self::Bisecting => "git.bisecting",
+            Self::Reverting { .. } => "git.reverting",
+            Self::CherryPicking { .. } => "git.cherry-picking",
+            Self::Merging { .. } => "git.merging",
+            Self::Rebasing { .. } => "git.rebasing",
+        }
+    }
+
+    fn builtin(&self, mode: IconMode) -> &'static str {
         use IconMode::*;
         match self {
             Self::Bisecting => match mode {
@@ -399,7 +456,31 @@ fn get_remote(head: &Head) -> Option<Remote> {
     })
 }
 
+/// Web page of the repository, from `remote.<name>.url` of the upstream or `origin`
+fn get_web_repo(head: &Head, remote: Option<&Remote>) -> Option<link::Repo> {
+    let name = remote.map_or("origin", |remote| remote.name.as_str());
+    let section = format!("[remote \"{name}\"]");
+    BufReader::new(File::open(head.root.join("config")).ok()?)
+        .lines()
+        .map_while(Result::ok)
+        .skip_while(|x| x != &section)
+        .skip(1)
+        .take_while(|x| x.starts_with('\t'))
+        .find_map(|line| line.strip_prefix("\turl = ").and_then(link::Repo::parse))
+}
+
+/// Link to the branch on remote if it is pushed there, or to the detached commit
+fn get_link(head: &Head, remote: Option<&Remote>) -> Option<String> {
+    let repo = get_web_repo(head, remote)?;
+    match (&head.kind, remote) {
+        (HeadKind::Branch(_), Some(remote)) if remote.exists => Some(repo.branch(&remote.branch)),
+        (HeadKind::Commit(id), _) => Some(repo.commit(id)),
+        _ => None,
+    }
+}
+
 fn get_ahead_behind(
+    environ: &Environment,
     tree: &Path,
     head: &HeadKind,
     remote: Option<&Remote>,
@@ -417,17 +498,14 @@ fn get_ahead_behind(
     };
 
     // This should not be that slow
-    let output = Command::new("git")
-        .arg("-C")
-        .arg(tree)
-        .arg("rev-list")
-        .arg("--count")
-        .arg("--left-right")
-        .arg(format!("{head}...{name}/{branch}"))
-        .output()
-        .ok()?;
+    let range = format!("{head}...{name}/{branch}");
+    let args = ["rev-list", "--count", "--left-right", &range];
+    let output = profile::span("git", "rev-list", || {
+        daemon::socket(environ)
+            .and_then(|socket| daemon::query(&socket, tree, &args))
+            .or_else(|| Some(environ.git(tree).args(args).output().ok()?.stdout))
+    })?;
     let mut iter = output
-        .stdout
         .trim_ascii_end()
         .split(|&c| c == b'\t')
         .flat_map(std::str::from_utf8)
@@ -440,6 +518,8 @@ fn get_ahead_behind(
 pub struct GitRepo {
     head: Head,
     remote: Option<Remote>,
+    /// Web page of the branch or commit
+    link: Option<String>,
     stashes: usize,
     state: Option<State>,
     behind: usize,
@@ -451,23 +531,11 @@ super::register_block!(GitRepo);
 impl Block for GitRepo {
     fn new(environ: &Environment) -> Option<Self> {
         let tree = environ.git_tree.as_ref()?.clone();
-        let dotgit = tree.join(".git");
-        let root = if dotgit.is_file() {
-            tree.join(
-                std::fs::read_to_string(&dotgit)
-                    .ok()?
-                    .strip_prefix("gitdir: ")?
-                    .trim_end_matches(['\r', '\n']),
-            )
-        } else {
-            dotgit
-        };
+        let root = file::git_dir(&tree)?;
 
         let stash_path = root.join("logs/refs/stash");
         // eprintln!("try find stashes in {stash_path:?}");
-        let stashes = File::open(stash_path)
-            .map(|file| BufReader::new(file).lines().count())
-            .unwrap_or(0);
+        let stashes = File::open(stash_path).map_or(0, |file| BufReader::new(file).lines().count());
 
         let state = State::discover(&root);
 
@@ -488,23 +556,58 @@ impl Block for GitRepo {
         let head = head.refine_unborn();
 
         let remote = get_remote(&head);
+        let link = link::enabled(environ)
+            .then(|| get_link(&head, remote.as_ref()))
+            .flatten();
 
         let (ahead, behind) =
-            get_ahead_behind(&tree, &head.kind, remote.as_ref()).unwrap_or((0, 0));
+            get_ahead_behind(environ, &tree, &head.kind, remote.as_ref()).unwrap_or((0, 0));
 
         Some(GitRepo {
             head,
             remote,
+            link,
             stashes,
             state,
             behind,
             ahead,
         })
     }
+
+    fn json(&self) -> Value {
+        Value::Object(vec![
+            ("head", self.head.json()),
+            (
+                "remote",
+                self.remote
+                    .as_ref()
+                    .map(|remote| {
+                        Value::Object(vec![
+                            ("name", remote.name.as_str().into()),
+                            ("branch", remote.branch.as_str().into()),
+                            ("exists", remote.exists.into()),
+                        ])
+                    })
+                    .into(),
+            ),
+            ("stashes", self.stashes.into()),
+            ("ahead", self.ahead.into()),
+            ("behind", self.behind.into()),
+            ("state", self.state.as_ref().map(State::json).into()),
+        ])
+    }
+
+    fn priority(&self) -> u8 {
+        85
+    }
 }
 
 pub struct GitTree {
     tree: PathBuf,
+    /// Socket of the daemon which may have status cached
+    daemon: Option<PathBuf>,
+    /// Git to run in the worktree if the daemon does not answer
+    git: Command,
     unmerged: usize,
     staged: usize,
     dirty: usize,
@@ -515,8 +618,11 @@ super::register_block!(GitTree);
 
 impl Block for GitTree {
     fn new(environ: &Environment) -> Option<Self> {
+        let tree = environ.git_tree.as_ref()?;
         Some(GitTree {
-            tree: environ.git_tree.as_ref()?.clone(),
+            tree: tree.clone(),
+            daemon: daemon::socket(environ),
+            git: environ.git(tree),
             unmerged: 0,
             staged: 0,
             dirty: 0,
@@ -525,27 +631,35 @@ impl Block for GitTree {
     }
 
     fn extend(&mut self) {
-        let parent_pid = rustix::process::getpid();
-        // SAFETY: pre_exec only sets parent process death signal and does nothing more
-        let out = unsafe {
-            Command::new("git")
-                .arg("-C")
-                .arg(&self.tree)
-                .arg("status")
-                .arg("--porcelain=2")
-                .pre_exec(move || -> IoResult<()> {
-                    rustix::process::set_parent_process_death_signal(Some(Signal::TERM))?;
-                    assert!(
-                        Some(parent_pid) == rustix::process::getppid(),
-                        "Parent already dead"
-                    );
-                    Ok(())
+        let args = ["status", "--porcelain=2"];
+        let out = profile::span("git", "status", || {
+            self.daemon
+                .as_ref()
+                .and_then(|socket| daemon::query(socket, &self.tree, &args))
+                .or_else(|| {
+                    let parent_pid = rustix::process::getpid();
+                    // SAFETY: pre_exec only sets parent process death signal and does nothing more
+                    unsafe {
+                        self.git
+                            .args(args)
+                            .pre_exec(move || -> IoResult<()> {
+                                rustix::process::set_parent_process_death_signal(Some(
+                                    Signal::TERM,
+                                ))?;
+                                assert!(
+                                    Some(parent_pid) == rustix::process::getppid(),
+                                    "Parent already dead"
+                                );
+                                Ok(())
+                            })
+                            .output()
+                            .ok()
+                            .map(|out| out.stdout)
+                    }
                 })
-                .output()
-                .ok()
-        };
+        });
         let Some(out) = out else { return };
-        let lines = out.stdout.split(|&c| c == b'\n');
+        let lines = out.split(|&c| c == b'\n');
 
         let mut unmerged = 0;
         let mut staged = 0;
@@ -582,18 +696,31 @@ impl Block for GitTree {
         self.dirty = dirty;
         self.untracked = untracked;
     }
+
+    fn json(&self) -> Value {
+        Value::Object(vec![
+            ("unmerged", self.unmerged.into()),
+            ("staged", self.staged.into()),
+            ("dirty", self.dirty.into()),
+            ("untracked", self.untracked.into()),
+        ])
+    }
+
+    fn priority(&self) -> u8 {
+        80
+    }
 }
 
 impl Pretty for GitRepo {
     fn pretty(&self, f: &mut std::fmt::Formatter<'_>, mode: IconMode) -> std::fmt::Result {
-        f.with_style(Color::of(&self.head.ref_name()), Style::BOLD, |f| {
-            write!(f, "[")?;
-
+        f.segment(Role::GitHead, &self.head.ref_name(), |f| {
             if let Some(state) = &self.state {
                 write!(f, "{}|", crate::icon::display(state, mode))?;
             }
 
-            write!(f, "{}", crate::icon::display(&self.head, mode))?;
+            f.with_link(self.link.as_deref(), |f| {
+                write!(f, "{}", crate::icon::display(&self.head, mode))
+            })?;
 
             if let HeadKind::Branch(local) = &self.head.kind
                 && let Some(Remote { branch: remote, .. }) = &self.remote
@@ -616,7 +743,7 @@ impl Pretty for GitRepo {
                 }
             }
 
-            write!(f, "]")
+            Ok(())
         })
     }
 }
@@ -627,9 +754,7 @@ impl Pretty for GitTree {
             return Ok(());
         }
 
-        f.with_style(Color::PINK, Style::empty(), |f| {
-            write!(f, "[")?;
-
+        f.segment(Role::GitDirty, "", |f| {
             let mut first = true;
             for (icon, val) in [
                 (GitIcon::Conflict, self.unmerged),
@@ -647,7 +772,7 @@ impl Pretty for GitTree {
                 }
             }
 
-            write!(f, "]")
+            Ok(())
         })
     }
 }
@@ -671,7 +796,19 @@ enum GitIcon {
 }
 
 impl Icon for GitIcon {
-    fn icon(&self, mode: IconMode) -> &'static str {
+    fn key(&self) -> &'static str {
+        match self {
+            Self::Ahead => "git.ahead",
+            Self::Behind => "git.behind",
+            Self::Stashes => "git.stashes",
+            Self::Conflict => "git.conflict",
+            Self::Staged => "git.staged",
+            Self::Dirty => "git.dirty",
+            Self::Untracked => "git.untracked",
+        }
+    }
+
+    fn builtin(&self, mode: IconMode) -> &'static str {
         use IconMode::*;
         match &self {
             Self::Ahead => match mode {
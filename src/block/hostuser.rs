@@ -1,6 +1,10 @@
-use crate::{Block, Chassis, Color, Environment, Icon, IconMode, Pretty, Style, WithStyle as _};
+use crate::{
+    Block, Chassis, Environment, Icon, IconMode, Pretty, Role, WithStyle as _,
+    json::Value,
+    virt::{self, Virtualization},
+};
 
-struct Host(Chassis, String);
+struct Host(Chassis, String, Option<Virtualization>);
 struct User(String);
 pub struct HostUser(User, Host);
 
@@ -8,7 +12,12 @@ super::register_block!(HostUser);
 
 impl Host {
     fn new(env: &Environment) -> Self {
-        Host(Chassis::get(), env.host.clone())
+        // Naming virtualization technology is opt-in, as it is not needed on most hosts
+        let virt = env
+            .var_os("PS1_VIRT")
+            .filter(|var| !var.is_empty())
+            .and_then(|_| virt::detect(&env.sysroot).ok().flatten());
+        Host(Chassis::get(&env.sysroot), env.host.clone(), virt)
     }
 }
 
@@ -22,16 +31,38 @@ impl Block for HostUser {
     fn new(environ: &Environment) -> Option<Self> {
         Some(HostUser(User::new(environ), Host::new(environ)))
     }
+
+    fn json(&self) -> Value {
+        let HostUser(User(user), Host(chassis, host, virt)) = self;
+        Value::Object(vec![
+            ("user", user.as_str().into()),
+            ("host", host.as_str().into()),
+            ("chassis", chassis.as_str().into()),
+            ("virtualization", virt.map(Virtualization::as_str).into()),
+        ])
+    }
+
+    fn priority(&self) -> u8 {
+        95
+    }
 }
 
 impl Icon for Host {
-    fn icon(&self, mode: IconMode) -> &'static str {
-        self.0.icon(mode)
+    fn key(&self) -> &'static str {
+        self.0.key()
+    }
+
+    fn builtin(&self, mode: IconMode) -> &'static str {
+        self.0.builtin(mode)
     }
 }
 
 impl Icon for User {
-    fn icon(&self, mode: IconMode) -> &'static str {
+    fn key(&self) -> &'static str {
+        "user"
+    }
+
+    fn builtin(&self, mode: IconMode) -> &'static str {
         use IconMode::*;
         match mode {
             Text => "as",
@@ -42,27 +73,30 @@ impl Icon for User {
 
 impl Pretty for Host {
     fn pretty(&self, f: &mut std::fmt::Formatter<'_>, mode: IconMode) -> std::fmt::Result {
-        f.with_style(Color::of(&self.1), Style::BOLD, |f| {
-            write!(f, "[{} {}]", self.icon(mode), self.1)
+        f.segment(Role::Host, &self.1, |f| {
+            write!(f, "{} {}", self.icon(mode), self.1)?;
+            if let Some(virt) = self.2.and_then(Virtualization::reported) {
+                write!(f, "|{}", virt.as_str())?;
+            }
+            Ok(())
         })
     }
 }
 
 impl Pretty for User {
     fn pretty(&self, f: &mut std::fmt::Formatter<'_>, mode: IconMode) -> std::fmt::Result {
-        f.with_style(Color::of(&self.0), Style::BOLD, |f| {
-            write!(f, "[{} {}]", self.icon(mode), self.0)
+        f.segment(Role::User, &self.0, |f| {
+            write!(f, "{} {}", self.icon(mode), self.0)
         })
     }
 }
 
 impl Pretty for HostUser {
     fn pretty(&self, f: &mut std::fmt::Formatter<'_>, mode: IconMode) -> std::fmt::Result {
-        write!(
-            f,
-            "{} {}",
-            crate::icon::display(&self.1, mode),
-            crate::icon::display(&self.0, mode)
-        )
+        write!(f, "{}", crate::icon::display(&self.1, mode))?;
+        if !crate::powerline::joining() {
+            write!(f, " ")?;
+        }
+        write!(f, "{}", crate::icon::display(&self.0, mode))
     }
 }
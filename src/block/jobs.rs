@@ -1,4 +1,4 @@
-use crate::{Block, Color, Environment, IconMode, Pretty, Style, WithStyle as _};
+use crate::{Block, Environment, IconMode, Pretty, Role, WithStyle as _, json::Value};
 
 pub struct Jobs(usize);
 
@@ -9,13 +9,21 @@ impl Block for Jobs {
         let count = environ.jobs_count.unwrap_or_default();
         (count > 0).then_some(Jobs(count))
     }
+
+    fn json(&self) -> Value {
+        Value::Object(vec![("count", self.0.into())])
+    }
+
+    fn priority(&self) -> u8 {
+        75
+    }
 }
 
 impl Pretty for Jobs {
     fn pretty(&self, f: &mut std::fmt::Formatter<'_>, _: IconMode) -> std::fmt::Result {
-        f.with_style(Color::GREEN, Style::BOLD, |f| {
+        f.segment(Role::Jobs, "", |f| {
             let text = if self.0 == 1 { "job" } else { "jobs" };
-            write!(f, "[{} {text}]", self.0)
+            write!(f, "{} {text}", self.0)
         })
     }
 }
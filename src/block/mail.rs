@@ -1,4 +1,4 @@
-use crate::{Block, Color, Environment, Icon, IconMode, Pretty, Style, WithStyle as _};
+use crate::{Block, Environment, Icon, IconMode, Pretty, Role, WithStyle as _, json::Value};
 use std::path::PathBuf;
 
 pub struct UnseenMail {
@@ -9,9 +9,14 @@ super::register_block!(UnseenMail);
 
 impl Block for UnseenMail {
     fn new(environ: &Environment) -> Option<Self> {
-        let maildir_path =
-            std::env::var("MAIL").unwrap_or_else(|_| format!("/var/spool/mail/{}", environ.user));
-        let maildir = PathBuf::from(maildir_path);
+        let maildir = environ.var_os("MAIL").map_or_else(
+            || {
+                environ
+                    .sysroot
+                    .path(format!("/var/spool/mail/{}", environ.user))
+            },
+            PathBuf::from,
+        );
         let unseen_count = ignore_errors(maildir.join("new").read_dir()).count();
         let unread_count = ignore_errors(maildir.join("cur").read_dir())
             .filter(|entry| entry.file_name().as_encoded_bytes().ends_with(b":2,"))
@@ -19,6 +24,14 @@ impl Block for UnseenMail {
         let count = unseen_count + unread_count;
         (count > 0).then_some(UnseenMail { count })
     }
+
+    fn json(&self) -> Value {
+        Value::Object(vec![("count", self.count.into())])
+    }
+
+    fn priority(&self) -> u8 {
+        40
+    }
 }
 
 fn ignore_errors<T, E>(
@@ -29,14 +42,18 @@ fn ignore_errors<T, E>(
 
 impl Pretty for UnseenMail {
     fn pretty(&self, f: &mut std::fmt::Formatter<'_>, mode: IconMode) -> std::fmt::Result {
-        f.with_style(Color::YELLOW, Style::empty(), |f| {
-            write!(f, "[{} {}]", self.icon(mode), self.count)
+        f.segment(Role::Mail, "", |f| {
+            write!(f, "{} {}", self.icon(mode), self.count)
         })
     }
 }
 
 impl Icon for UnseenMail {
-    fn icon(&self, mode: IconMode) -> &'static str {
+    fn key(&self) -> &'static str {
+        "mail"
+    }
+
+    fn builtin(&self, mode: IconMode) -> &'static str {
         use IconMode::*;
         match &mode {
             Text => "eml",
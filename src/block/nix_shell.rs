@@ -1,4 +1,4 @@
-use crate::{Block, Color, Environment, Icon, IconMode, Pretty, Style, WithStyle as _};
+use crate::{Block, Environment, Icon, IconMode, Pretty, Role, WithStyle as _, json::Value};
 use std::{ffi::OsStr, path::Path};
 
 pub struct NixShell {
@@ -7,13 +7,15 @@ pub struct NixShell {
     purity: bool,
     // buildInputs=/nix/store/HASH-derivation /nix/store/HASH-derivation ...
     inputs: Vec<String>,
+    /// Inputs are not shown
+    compact: bool,
 }
 
 super::register_block!(NixShell);
 
 impl Block for NixShell {
-    fn new(_: &Environment) -> Option<Self> {
-        let purity = match std::env::var("IN_NIX_SHELL").ok()?.as_ref() {
+    fn new(environ: &Environment) -> Option<Self> {
+        let purity = match environ.var("IN_NIX_SHELL")? {
             "impure" => false,
             "pure" => true,
 
@@ -22,11 +24,13 @@ impl Block for NixShell {
         };
 
         // XXX: Do we also need propagatedXxxInputs?
-        let inputs = std::env::var("buildInputs")
+        let inputs = environ
+            .var("buildInputs")
             .unwrap_or_default()
             .split_whitespace()
             .chain(
-                std::env::var("nativeBuildInputs")
+                environ
+                    .var("nativeBuildInputs")
                     .unwrap_or_default()
                     .split_whitespace(),
             )
@@ -38,26 +42,45 @@ impl Block for NixShell {
             .map(|(h, p)| format!("{}:{p}", &h[..6]))
             .collect();
 
-        Some(NixShell { purity, inputs })
+        Some(NixShell {
+            purity,
+            inputs,
+            compact: false,
+        })
+    }
+
+    fn json(&self) -> Value {
+        Value::Object(vec![
+            ("pure", self.purity.into()),
+            ("inputs", self.inputs.clone().into()),
+        ])
+    }
+
+    fn compact(&mut self) -> bool {
+        !self.inputs.is_empty() && !std::mem::replace(&mut self.compact, true)
     }
 }
 
 impl Pretty for NixShell {
     fn pretty(&self, f: &mut std::fmt::Formatter<'_>, mode: IconMode) -> std::fmt::Result {
-        f.with_style(Color::BRIGHT_BLUE, Style::empty(), |f| {
+        f.segment(Role::Nix, "", |f| {
             let purity = if self.purity { "" } else { "!" };
-            write!(f, "[{purity}{}", self.icon(mode))?;
-            for input in &self.inputs {
+            write!(f, "{purity}{}", self.icon(mode))?;
+            let inputs = if self.compact { &[][..] } else { &self.inputs };
+            for input in inputs {
                 write!(f, " {input}")?;
             }
-            write!(f, "]")?;
             Ok(())
         })
     }
 }
 
 impl Icon for NixShell {
-    fn icon(&self, mode: IconMode) -> &'static str {
+    fn key(&self) -> &'static str {
+        "nix"
+    }
+
+    fn builtin(&self, mode: IconMode) -> &'static str {
         use IconMode::*;
         match &mode {
             Text => "nix",
@@ -1,4 +1,4 @@
-use crate::{Block, Color, Environment, Icon, IconMode, Pretty, Style, WithStyle as _};
+use crate::{Block, Environment, Icon, IconMode, Pretty, Role, WithStyle as _, json::Value};
 use linux_raw_sys::general::{_NSIG as SIGRTMAX, SIGRTMIN};
 use rustix::process::Signal;
 
@@ -23,10 +23,38 @@ impl Block for ReturnCode {
             },
         })
     }
+
+    fn json(&self) -> Value {
+        match self {
+            Self::Ok => Value::Object(vec![("status", "ok".into()), ("code", 0_u64.into())]),
+            Self::Failed(code) => Value::Object(vec![
+                ("status", "failed".into()),
+                ("code", u64::from(*code).into()),
+            ]),
+            Self::Signaled(sig) => Value::Object(vec![
+                ("status", "signaled".into()),
+                ("signal", sig.as_str().into()),
+            ]),
+            Self::NotAvailable => Value::Object(vec![("status", "unavailable".into())]),
+        }
+    }
+
+    fn priority(&self) -> u8 {
+        90
+    }
 }
 
 impl Icon for ReturnCode {
-    fn icon(&self, mode: IconMode) -> &'static str {
+    fn key(&self) -> &'static str {
+        match self {
+            Self::Ok => "return.ok",
+            Self::Failed(..) => "return.fail",
+            Self::Signaled(..) => "return.signal",
+            Self::NotAvailable => "return.unavailable",
+        }
+    }
+
+    fn builtin(&self, mode: IconMode) -> &'static str {
         use IconMode::*;
         match &self {
             Self::Ok => match &mode {
@@ -50,11 +78,11 @@ impl Pretty for ReturnCode {
     fn pretty(&self, f: &mut std::fmt::Formatter<'_>, mode: IconMode) -> std::fmt::Result {
         let icon = self.icon(mode);
 
-        let color = match &self {
-            Self::Ok => Color::LIGHT_GREEN,
-            Self::Failed(..) => Color::LIGHT_RED,
-            Self::Signaled(..) => Color::TRUE_YELLOW,
-            Self::NotAvailable => Color::LIGHT_GRAY,
+        let role = match &self {
+            Self::Ok => Role::ReturnOk,
+            Self::Failed(..) => Role::ReturnFail,
+            Self::Signaled(..) => Role::ReturnSignal,
+            Self::NotAvailable => Role::ReturnUnavailable,
         };
 
         if let Self::Ok | Self::NotAvailable = self
@@ -63,7 +91,7 @@ impl Pretty for ReturnCode {
             return Ok(());
         }
 
-        f.with_style(color, Style::empty(), |f| match &self {
+        f.with_role(role, |f| match &self {
             Self::Ok | Self::NotAvailable => write!(f, "{icon}"),
             Self::Failed(code) => write!(f, "{code}{icon}"),
             Self::Signaled(sig) => write!(f, "{icon}{sig}"),
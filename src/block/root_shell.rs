@@ -1,4 +1,4 @@
-use crate::{Block, Color, Environment, Icon, IconMode, Pretty, Style, WithStyle as _};
+use crate::{Block, Environment, Icon, IconMode, Pretty, Role, WithStyle as _, json::Value};
 
 pub struct RootShell {
     is_root: bool,
@@ -8,32 +8,52 @@ pub struct RootShell {
 super::register_block!(RootShell);
 
 impl Block for RootShell {
-    fn new(_: &Environment) -> Option<Self> {
+    fn new(environ: &Environment) -> Option<Self> {
         Some(RootShell {
-            is_root: rustix::process::getuid().is_root(),
-            depth: std::env::var("SHLVL")
+            is_root: environ.is_root,
+            depth: environ
+                .var("SHLVL")
                 .unwrap_or_default()
                 .parse()
                 .unwrap_or_default(),
         })
     }
+
+    fn json(&self) -> Value {
+        Value::Object(vec![
+            ("root", self.is_root.into()),
+            ("depth", self.depth.into()),
+        ])
+    }
+
+    fn priority(&self) -> u8 {
+        u8::MAX
+    }
 }
 
 impl Icon for RootShell {
-    fn icon(&self, _: IconMode) -> &'static str {
+    fn key(&self) -> &'static str {
+        if self.is_root {
+            "shell.root"
+        } else {
+            "shell.user"
+        }
+    }
+
+    fn builtin(&self, _: IconMode) -> &'static str {
         if self.is_root { "#" } else { "$" }
     }
 }
 
 impl Pretty for RootShell {
     fn pretty(&self, f: &mut std::fmt::Formatter<'_>, mode: IconMode) -> std::fmt::Result {
-        let color = if self.is_root {
-            Color::RED
+        let role = if self.is_root {
+            Role::ShellRoot
         } else {
-            Color::GREEN
+            Role::ShellUser
         };
 
-        f.with_style(color, Style::empty(), |f| {
+        f.with_role(role, |f| {
             if self.depth > 0 {
                 write!(f, "{}", 1 + self.depth)?;
             }
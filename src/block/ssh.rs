@@ -1,21 +1,50 @@
 use crate::{
-    Block, Color, Environment, Icon, IconMode, Pretty, Style, WithStyle as _,
+    Block, Environment, Icon, IconMode, Pretty, Role, WithStyle as _,
+    json::Value,
     workgroup::{SshChain, WorkgroupKey},
 };
 
-pub struct Ssh(Vec<String>);
+pub struct Ssh {
+    chain: Vec<String>,
+    /// Only the last host of the chain is shown
+    compact: bool,
+}
 
 super::register_block!(Ssh);
 
 impl Block for Ssh {
-    fn new(_: &Environment) -> Option<Self> {
-        let chain = SshChain::open(WorkgroupKey::load().ok().as_ref()).0;
-        (!chain.is_empty()).then_some(Ssh(chain))
+    fn new(environ: &Environment) -> Option<Self> {
+        let chain = SshChain::open(
+            WorkgroupKey::load(environ.var_os("HOME")).ok().as_ref(),
+            environ.var("WORKGROUP_CHAIN"),
+            environ.var("SSH_CONNECTION"),
+        )
+        .0;
+        (!chain.is_empty()).then_some(Ssh {
+            chain,
+            compact: false,
+        })
+    }
+
+    fn json(&self) -> Value {
+        Value::Object(vec![("chain", self.chain.clone().into())])
+    }
+
+    fn priority(&self) -> u8 {
+        30
+    }
+
+    fn compact(&mut self) -> bool {
+        self.chain.len() > 1 && !std::mem::replace(&mut self.compact, true)
     }
 }
 
 impl Icon for Ssh {
-    fn icon(&self, mode: IconMode) -> &'static str {
+    fn key(&self) -> &'static str {
+        "ssh"
+    }
+
+    fn builtin(&self, mode: IconMode) -> &'static str {
         use IconMode::*;
         match mode {
             Text => "ssh",
@@ -26,12 +55,18 @@ impl Icon for Ssh {
 
 impl Pretty for Ssh {
     fn pretty(&self, f: &mut std::fmt::Formatter<'_>, mode: IconMode) -> std::fmt::Result {
-        f.with_style(Color::CYAN, Style::empty(), |f| {
-            write!(f, "[{}", self.icon(mode))?;
-            for link in &self.0 {
-                write!(f, " {link}")?;
+        f.segment(Role::Ssh, "", |f| {
+            write!(f, "{}", self.icon(mode))?;
+            if self.compact
+                && let Some(last) = self.chain.last()
+            {
+                write!(f, " {}{last}", crate::icon::ellipsis(mode))?;
+            } else {
+                for link in &self.chain {
+                    write!(f, " {link}")?;
+                }
             }
-            write!(f, "]")
+            Ok(())
         })
     }
 }
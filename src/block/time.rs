@@ -1,20 +1,52 @@
-use crate::{Block, Color, Environment, IconMode, Pretty, Style, WithStyle as _};
+use crate::{Block, Environment, IconMode, Pretty, Role, WithStyle as _, json::Value};
 use chrono::prelude::*;
 
-pub struct Time(DateTime<Local>);
+pub struct Time {
+    now: DateTime<FixedOffset>,
+    /// Only time of day is shown
+    compact: bool,
+}
 
 super::register_block!(Time);
 
 impl Block for Time {
-    fn new(_: &Environment) -> Option<Self> {
-        Some(Self(Local::now()))
+    fn new(environ: &Environment) -> Option<Self> {
+        Some(Time {
+            now: environ.now,
+            compact: false,
+        })
+    }
+
+    fn json(&self) -> Value {
+        Value::Object(vec![
+            ("rfc3339", self.now.to_rfc3339().into()),
+            (
+                "unix",
+                u64::try_from(self.now.timestamp())
+                    .unwrap_or_default()
+                    .into(),
+            ),
+        ])
+    }
+
+    fn priority(&self) -> u8 {
+        10
+    }
+
+    fn compact(&mut self) -> bool {
+        !std::mem::replace(&mut self.compact, true)
     }
 }
 
 impl Pretty for Time {
     fn pretty(&self, f: &mut std::fmt::Formatter<'_>, _: IconMode) -> std::fmt::Result {
-        f.with_style(Color::GRAY, Style::empty(), |f| {
-            write!(f, "{}", self.0.format("%a, %Y-%b-%d, %H:%M:%S in %Z"))
+        f.with_role(Role::Time, |f| {
+            let format = if self.compact {
+                "%H:%M:%S"
+            } else {
+                "%a, %Y-%b-%d, %H:%M:%S in %Z"
+            };
+            write!(f, "{}", self.now.format(format))
         })
     }
 }
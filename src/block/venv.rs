@@ -1,4 +1,4 @@
-use crate::{Block, Color, Environment, Icon, IconMode, Pretty, Style, WithStyle as _};
+use crate::{Block, Environment, Icon, IconMode, Pretty, Role, WithStyle as _, json::Value};
 use anyhow::Result;
 use std::{
     ffi::OsStr,
@@ -15,8 +15,8 @@ pub struct Venv {
 super::register_block!(Venv);
 
 impl Block for Venv {
-    fn new(_: &Environment) -> Option<Self> {
-        let path = PathBuf::from(std::env::var("VIRTUAL_ENV").ok()?);
+    fn new(environ: &Environment) -> Option<Self> {
+        let path = PathBuf::from(environ.var_os("VIRTUAL_ENV")?);
         Some(Venv {
             name: venv_name(&path).to_owned(),
             version: venv_ver(&path)
@@ -24,18 +24,33 @@ impl Block for Venv {
                 .unwrap_or("<sys?>".to_owned()),
         })
     }
+
+    fn json(&self) -> Value {
+        Value::Object(vec![
+            ("name", self.name.as_str().into()),
+            ("version", self.version.as_str().into()),
+        ])
+    }
+
+    fn priority(&self) -> u8 {
+        60
+    }
 }
 
 impl Pretty for Venv {
     fn pretty(&self, f: &mut std::fmt::Formatter, mode: IconMode) -> std::fmt::Result {
-        f.with_style(Color::YELLOW, Style::empty(), |f| {
-            write!(f, "[{} {}|{}]", self.icon(mode), self.version, self.name)
+        f.segment(Role::Venv, "", |f| {
+            write!(f, "{} {}|{}", self.icon(mode), self.version, self.name)
         })
     }
 }
 
 impl Icon for Venv {
-    fn icon(&self, mode: IconMode) -> &'static str {
+    fn key(&self) -> &'static str {
+        "venv"
+    }
+
+    fn builtin(&self, mode: IconMode) -> &'static str {
         use IconMode::*;
         match &mode {
             Text => "py",
@@ -1,8 +1,8 @@
-use crate::{Block, Color, Environment, Icon, IconMode, Pretty, Style, WithStyle as _};
+use crate::{Block, Environment, Icon, IconMode, Pretty, Role, WithStyle as _, json::Value};
 use anyhow::{Context as _, Result, ensure};
 use rustix::fs::{Access, Stat};
 use std::{
-    ffi::OsString,
+    ffi::{OsStr, OsString},
     os::unix::ffi::OsStringExt as _,
     path::{Path, PathBuf},
 };
@@ -15,8 +15,30 @@ enum State {
     NoAccess,
 }
 
+impl State {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Writeable => "writeable",
+            Self::Readable => "readonly",
+            Self::Moved => "moved",
+            Self::Deleted => "deleted",
+            Self::NoAccess => "forbidden",
+        }
+    }
+}
+
 impl Icon for State {
-    fn icon(&self, mode: IconMode) -> &'static str {
+    fn key(&self) -> &'static str {
+        match self {
+            Self::Writeable => "workdir.writeable",
+            Self::Readable => "workdir.readonly",
+            Self::Deleted => "workdir.deleted",
+            Self::Moved => "workdir.moved",
+            Self::NoAccess => "workdir.forbidden",
+        }
+    }
+
+    fn builtin(&self, mode: IconMode) -> &'static str {
         use IconMode::*;
         match self {
             Self::Writeable => "",
@@ -42,9 +64,7 @@ impl Icon for State {
 
 impl Pretty for State {
     fn pretty(&self, f: &mut std::fmt::Formatter, mode: IconMode) -> std::fmt::Result {
-        f.with_style(Color::RED, Style::ITALIC, |f| {
-            write!(f, "{}", self.icon(mode))
-        })
+        f.with_role(Role::WorkdirState, |f| write!(f, "{}", self.icon(mode)))
     }
 }
 
@@ -57,15 +77,15 @@ fn get_cwd_if_deleted() -> Option<PathBuf> {
     Some(PathBuf::from(OsString::from_vec(cwd)))
 }
 
-fn ensure_work_dir_not_moved(work_dir: &Path, stat_dot: Stat) -> Result<()> {
+fn ensure_work_dir_not_moved(work_dir: &Path, stat_dot: Stat, pwd: Option<&OsStr>) -> Result<()> {
     let stat_pwd = rustix::fs::stat(work_dir)?;
     ensure!((stat_dot.st_dev, stat_dot.st_ino) == (stat_pwd.st_dev, stat_pwd.st_ino));
-    ensure!(*work_dir == std::env::var_os("PWD").context("No PWD")?);
+    ensure!(*work_dir == pwd.context("No PWD")?);
     Ok(())
 }
 
-fn get_state(work_dir: &mut PathBuf) -> State {
-    let Ok(stat_dot) = rustix::fs::stat(".") else {
+fn get_state(environ: &Environment, work_dir: &mut PathBuf) -> State {
+    let Ok(stat_dot) = rustix::fs::stat(&environ.cwd) else {
         return State::NoAccess;
     };
 
@@ -84,7 +104,7 @@ fn get_state(work_dir: &mut PathBuf) -> State {
         return State::Deleted;
     }
 
-    if ensure_work_dir_not_moved(work_dir, stat_dot).is_err() {
+    if ensure_work_dir_not_moved(work_dir, stat_dot, environ.var_os("PWD")).is_err() {
         return State::Moved;
     }
 
@@ -94,11 +114,143 @@ fn get_state(work_dir: &mut PathBuf) -> State {
     }
 }
 
+/// How the path leading to the highlighted part is shortened, set by `PS1_WORKDIR`
+#[derive(Clone, Copy)]
+enum Strategy {
+    /// Path as is
+    Full,
+    /// Intermediate directories are abbreviated to their first letter, as fish shell does
+    Fish,
+    /// First directory and last N components are kept, with ellipsis between them
+    Keep(usize),
+    /// Intermediate directories are abbreviated to the shortest prefix unique among siblings
+    Unique,
+    /// Only name of git root is kept, other directories are abbreviated outside of git repos
+    Repo,
+    /// Only the last component is kept
+    Tail,
+}
+
+impl Strategy {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "full" => Some(Self::Full),
+            "fish" => Some(Self::Fish),
+            "unique" => Some(Self::Unique),
+            "repo" => Some(Self::Repo),
+            "keep" => Some(Self::Keep(1)),
+            _ => name
+                .strip_prefix("keep:")
+                .and_then(|count| count.parse().ok())
+                .filter(|&count| count > 0)
+                .map(Self::Keep),
+        }
+    }
+
+    /// Shortens `path`, which is relative to `base` unless absolute
+    fn apply(self, path: &Path, base: &Path, ellipsis: &str) -> String {
+        let full = path.display().to_string();
+        let root = if path.is_absolute() { "/" } else { "" };
+        let names: Vec<&str> = full.split('/').filter(|name| !name.is_empty()).collect();
+        let Some((last, dirs)) = names.split_last() else {
+            return full;
+        };
+        if dirs.is_empty() {
+            return full;
+        }
+        let mut res = root.to_owned();
+        match self {
+            Self::Full => return full,
+            Self::Fish => {
+                for dir in dirs {
+                    res.push_str(abbreviate(dir, 1));
+                    res.push('/');
+                }
+            }
+            Self::Unique => {
+                let mut parent = base.join(root);
+                for dir in dirs {
+                    res.push_str(unique_prefix(&parent, dir));
+                    res.push('/');
+                    parent.push(dir);
+                }
+            }
+            Self::Keep(count) => {
+                if names.len() <= count + 1 {
+                    return full;
+                }
+                res.push_str(names[0]);
+                res.push('/');
+                res.push_str(ellipsis);
+                res.push('/');
+                res.push_str(&names[names.len() - count..].join("/"));
+                return res;
+            }
+            Self::Repo | Self::Tail => return format!("{ellipsis}/{last}"),
+        }
+        res.push_str(last);
+        res
+    }
+}
+
+/// First `len` letters of the name, not counting the dot of hidden directories
+fn abbreviate(name: &str, len: usize) -> &str {
+    let len = len + usize::from(name.starts_with('.'));
+    name.char_indices()
+        .nth(len)
+        .map_or(name, |(end, _)| &name[..end])
+}
+
+/// Shortest abbreviation of `name` which no other entry in `parent` starts with
+fn unique_prefix<'a>(parent: &Path, name: &'a str) -> &'a str {
+    let Ok(entries) = std::fs::read_dir(parent) else {
+        return abbreviate(name, 1);
+    };
+    let siblings: Vec<OsString> = entries
+        .filter_map(|entry| Some(entry.ok()?.file_name()))
+        .filter(|sibling| sibling != name)
+        .collect();
+    (1..=name.chars().count())
+        .map(|len| abbreviate(name, len))
+        .find(|prefix| {
+            !siblings
+                .iter()
+                .any(|sibling| sibling.as_encoded_bytes().starts_with(prefix.as_bytes()))
+        })
+        .unwrap_or(name)
+}
+
 pub struct Workdir {
     work_dir: PathBuf,
     git_tree: Option<PathBuf>,
     current_home: Option<(PathBuf, String)>,
     state: State,
+    strategy: Strategy,
+    /// `file://` link to the directory
+    link: Option<String>,
+    /// How much path is shortened, the part outside of git tree goes first
+    compact: u8,
+}
+
+/// Most compact level, at which both parts are shortened to their last component
+const MOST_COMPACT: u8 = 4;
+
+/// Strategy which compaction `level` requires at least
+fn compacted(level: u8) -> Strategy {
+    match level {
+        0 => Strategy::Full,
+        1 => Strategy::Fish,
+        _ => Strategy::Tail,
+    }
+}
+
+/// Shorter of the two
+fn shorter(one: String, other: String) -> String {
+    if other.chars().count() < one.chars().count() {
+        other
+    } else {
+        one
+    }
 }
 
 super::register_block!(Workdir);
@@ -108,14 +260,53 @@ impl Block for Workdir {
         let mut work_dir = environ.work_dir.clone();
         let git_tree = environ.git_tree.clone();
         let current_home = environ.current_home.clone();
-        let state = get_state(&mut work_dir);
+        let state = get_state(environ, &mut work_dir);
+        let strategy = environ
+            .var("PS1_WORKDIR")
+            .and_then(Strategy::from_name)
+            .unwrap_or(Strategy::Full);
+        let link =
+            crate::link::enabled(environ).then(|| crate::link::file(&environ.host, &work_dir));
         Some(Workdir {
             work_dir,
             git_tree,
             current_home,
             state,
+            strategy,
+            link,
+            compact: 0,
         })
     }
+
+    fn json(&self) -> Value {
+        let path = |path: &Path| Value::from(path.to_string_lossy().into_owned());
+        Value::Object(vec![
+            ("path", path(&self.work_dir)),
+            ("state", self.state.as_str().into()),
+            ("git_tree", self.git_tree.as_deref().map(path).into()),
+            (
+                "home",
+                self.current_home
+                    .as_ref()
+                    .map(|(home, user)| {
+                        Value::Object(vec![("path", path(home)), ("user", user.as_str().into())])
+                    })
+                    .into(),
+            ),
+        ])
+    }
+
+    fn priority(&self) -> u8 {
+        u8::MAX
+    }
+
+    fn compact(&mut self) -> bool {
+        if self.compact == MOST_COMPACT {
+            return false;
+        }
+        self.compact += 1;
+        true
+    }
 }
 
 impl Pretty for Workdir {
@@ -144,26 +335,98 @@ impl Pretty for Workdir {
             otherwise => otherwise,
         };
 
+        let ellipsis = crate::icon::ellipsis(mode);
         write!(f, "{}", crate::icon::display(&self.state, mode))?;
 
-        if let Some((_, user)) = &self.current_home {
-            f.with_style(Color::YELLOW, Style::BOLD, |f| write!(f, "~{user}"))?;
-        }
-        // Add / between home and middle only if both are present
-        if self.current_home.is_some() && middle.is_some() {
-            write!(f, "/")?;
-        }
-        if let Some(middle) = middle {
-            write!(f, "{}", middle.display())?;
-        }
+        f.with_link(self.link.as_deref(), |f| {
+            if let Some((_, user)) = &self.current_home {
+                f.with_role(Role::WorkdirHome, |f| write!(f, "~{user}"))?;
+            }
+            // Add / between home and middle only if both are present
+            if self.current_home.is_some() && middle.is_some() {
+                write!(f, "/")?;
+            }
+            if let Some(middle) = middle {
+                let base = self
+                    .current_home
+                    .as_ref()
+                    .map_or(Path::new("/"), |(home, _)| home.as_path());
+                let strategy = match self.strategy {
+                    Strategy::Repo if self.git_tree.is_none() => Strategy::Fish,
+                    other => other,
+                };
+                let middle = shorter(
+                    strategy.apply(middle, base, ellipsis),
+                    compacted(self.compact).apply(middle, base, ellipsis),
+                );
+                write!(f, "{middle}")?;
+            }
+
+            // Always add / before highlighted
+            if let Some(high) = highlighted {
+                let high =
+                    compacted(self.compact.saturating_sub(2)).apply(high, Path::new(""), ellipsis);
+                f.with_role(Role::WorkdirRepo, |f| write!(f, "/{high}"))?;
+            }
+            Ok(())
+        })
+    }
+}
 
-        // Always add / before highlighted
-        if let Some(high) = highlighted {
-            f.with_style(Color::CYAN, Style::empty(), |f| {
-                write!(f, "/{}", high.display())
-            })?;
+#[cfg(test)]
+mod tests {
+    use super::{MOST_COMPACT, Strategy, compacted};
+    use std::path::Path;
+
+    #[test]
+    fn shortens_paths() {
+        let deep = "/home/user/src/statusline/block";
+        for (name, path, expected) in [
+            ("full", deep, Some(deep)),
+            ("fish", deep, Some("/h/u/s/s/block")),
+            ("fish", "src/statusline/block", Some("s/s/block")),
+            (
+                "fish",
+                "/home/user/.config/statusline",
+                Some("/h/u/.c/statusline"),
+            ),
+            ("fish", "/srv/über/data", Some("/s/ü/data")),
+            ("keep", deep, Some("/home/…/block")),
+            ("keep:1", deep, Some("/home/…/block")),
+            ("keep:3", deep, Some("/home/…/src/statusline/block")),
+            ("keep:4", deep, Some(deep)),
+            ("keep:9", deep, Some(deep)),
+            ("keep:0", deep, None),
+            ("keep:", deep, None),
+            ("keep:two", deep, None),
+            ("repo", deep, Some("…/block")),
+            ("fishy", deep, None),
+            // Single component is never shortened
+            ("fish", "/home", Some("/home")),
+            ("keep", "/home", Some("/home")),
+            ("repo", "/home", Some("/home")),
+            ("repo", "src", Some("src")),
+            ("fish", "/", Some("/")),
+        ] {
+            let shortened = Strategy::from_name(name)
+                .map(|strategy| strategy.apply(Path::new(path), Path::new("/"), "…"));
+            assert_eq!(shortened.as_deref(), expected, "{name} {path}");
         }
+    }
 
-        Ok(())
+    #[test]
+    fn compacts_levels() {
+        let path = Path::new("src/statusline/block");
+        let apply = |level| compacted(level).apply(path, Path::new(""), "…");
+        for (level, middle, highlighted) in [
+            (0, "src/statusline/block", "src/statusline/block"),
+            (1, "s/s/block", "src/statusline/block"),
+            (2, "…/block", "src/statusline/block"),
+            (3, "…/block", "s/s/block"),
+            (MOST_COMPACT, "…/block", "…/block"),
+        ] {
+            assert_eq!(apply(level), middle, "{level}");
+            assert_eq!(apply(level.saturating_sub(2)), highlighted, "{level}");
+        }
     }
 }
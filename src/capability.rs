@@ -0,0 +1,204 @@
+//! Detection of colors terminal can show, from environment and terminfo
+
+use crate::{style::ColorDepth, sysroot::Sysroot};
+use std::path::PathBuf;
+
+/// Magic numbers of compiled terminfo, with 16-bit and 32-bit numbers
+const TERMINFO_MAGIC: u16 = 0o432;
+const TERMINFO_MAGIC_32: u16 = 0o1036;
+/// Index of `colors` among numeric capabilities
+const COLORS: usize = 13;
+
+/// Color depth as told by `NO_COLOR`, `CLICOLOR_FORCE`, `COLORTERM`, `TERM` and its terminfo
+pub fn color_depth(var: impl Fn(&str) -> Option<String>, root: &Sysroot) -> ColorDepth {
+    let set = |name| var(name).filter(|value| !value.is_empty());
+    let forced = set("CLICOLOR_FORCE").is_some_and(|value| value != "0");
+    if !forced && set("NO_COLOR").is_some() {
+        return ColorDepth::None;
+    }
+    let depth = detect(&var, root);
+    if forced {
+        depth.max(ColorDepth::Ansi16)
+    } else {
+        depth
+    }
+}
+
+fn detect(var: &impl Fn(&str) -> Option<String>, root: &Sysroot) -> ColorDepth {
+    if let Some("truecolor" | "24bit") = var("COLORTERM").as_deref() {
+        return ColorDepth::True;
+    }
+    let Some(term) = var("TERM").filter(|term| !term.is_empty()) else {
+        return ColorDepth::None;
+    };
+    if term == "dumb" {
+        return ColorDepth::None;
+    }
+    if term.ends_with("-direct") {
+        return ColorDepth::True;
+    }
+    if term.ends_with("-256color") {
+        return ColorDepth::Ansi256;
+    }
+    match terminfo_colors(var, root, &term) {
+        Some(colors) if colors >= 1 << 24 => ColorDepth::True,
+        Some(colors) if colors >= 256 => ColorDepth::Ansi256,
+        Some(colors) if colors >= 8 => ColorDepth::Ansi16,
+        Some(_) => ColorDepth::None,
+        // Linux console and most of others know at least basic colors
+        None => ColorDepth::Ansi16,
+    }
+}
+
+/// Directories searched for terminfo, in the same order as ncurses does
+fn terminfo_dirs(var: &impl Fn(&str) -> Option<String>) -> Vec<PathBuf> {
+    let mut dirs = vec![];
+    if let Some(dir) = var("TERMINFO") {
+        dirs.push(PathBuf::from(dir));
+    }
+    if let Some(home) = var("HOME") {
+        dirs.push(PathBuf::from(home).join(".terminfo"));
+    }
+    if let Some(list) = var("TERMINFO_DIRS") {
+        dirs.extend(
+            list.split(':')
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from),
+        );
+    }
+    dirs.extend(
+        ["/etc/terminfo", "/lib/terminfo", "/usr/share/terminfo"]
+            .into_iter()
+            .map(PathBuf::from),
+    );
+    dirs
+}
+
+fn terminfo_colors(
+    var: &impl Fn(&str) -> Option<String>,
+    root: &Sysroot,
+    term: &str,
+) -> Option<u32> {
+    if term.contains('/') {
+        return None;
+    }
+    let first = term.chars().next()?;
+    terminfo_dirs(var).into_iter().find_map(|dir| {
+        // Some systems use hex of the first letter instead of the letter itself
+        [first.to_string(), format!("{:x}", u32::from(first))]
+            .into_iter()
+            .find_map(|sub| std::fs::read(root.path(dir.join(sub).join(term))).ok())
+            .and_then(|compiled| parse_colors(&compiled))
+    })
+}
+
+/// Reads `colors` from compiled terminfo entry
+fn parse_colors(compiled: &[u8]) -> Option<u32> {
+    let short = |idx: usize| {
+        let bytes = compiled.get(2 * idx..2 * idx + 2)?;
+        Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+    };
+    let number_size = match short(0)? {
+        TERMINFO_MAGIC => 2,
+        TERMINFO_MAGIC_32 => 4,
+        _ => return None,
+    };
+    let names_size = usize::from(short(1)?);
+    let bools_count = usize::from(short(2)?);
+    let numbers_count = usize::from(short(3)?);
+    if numbers_count <= COLORS {
+        return None;
+    }
+    let mut numbers = 12 + names_size + bools_count;
+    // Numbers are aligned to even offset
+    numbers += numbers % 2;
+    let start = numbers + COLORS * number_size;
+    let bytes = compiled.get(start..start + number_size)?;
+    let colors = if number_size == 2 {
+        i32::from(i16::from_le_bytes([bytes[0], bytes[1]]))
+    } else {
+        i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    };
+    // Negative means absent
+    u32::try_from(colors).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sysroot;
+
+    /// Compiled terminfo entry with only `colors` set
+    fn terminfo(colors: i16) -> Vec<u8> {
+        let names = b"test|test terminal\0";
+        let mut compiled = vec![];
+        for short in [
+            TERMINFO_MAGIC,
+            u16::try_from(names.len()).unwrap(),
+            1,
+            14,
+            0,
+            0,
+        ] {
+            compiled.extend(short.to_le_bytes());
+        }
+        compiled.extend(names);
+        compiled.push(0);
+        if compiled.len() % 2 == 1 {
+            compiled.push(0);
+        }
+        for idx in 0..14 {
+            let number = if idx == COLORS { colors } else { -1 };
+            compiled.extend(number.to_le_bytes());
+        }
+        compiled
+    }
+
+    #[test]
+    fn detects_depth() {
+        let root = sysroot::fixture("capability", None, &[("usr/share/terminfo/", "")]);
+        for (term, colors) in [("mono", 2), ("eight", 8), ("many", 256)] {
+            let dir = root.path("/usr/share/terminfo").join(&term[..1]);
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join(term), terminfo(colors)).unwrap();
+        }
+        for (vars, depth) in [
+            (&[][..], ColorDepth::None),
+            (&[("TERM", "dumb")], ColorDepth::None),
+            (&[("TERM", "linux")], ColorDepth::Ansi16),
+            (&[("TERM", "xterm-256color")], ColorDepth::Ansi256),
+            (&[("TERM", "xterm-direct")], ColorDepth::True),
+            (&[("TERM", "mono")], ColorDepth::None),
+            (&[("TERM", "eight")], ColorDepth::Ansi16),
+            (&[("TERM", "many")], ColorDepth::Ansi256),
+            (
+                &[("TERM", "linux"), ("COLORTERM", "truecolor")],
+                ColorDepth::True,
+            ),
+            (
+                &[("TERM", "xterm-256color"), ("NO_COLOR", "1")],
+                ColorDepth::None,
+            ),
+            (
+                &[("TERM", "xterm-256color"), ("NO_COLOR", "")],
+                ColorDepth::Ansi256,
+            ),
+            (
+                &[("TERM", "dumb"), ("NO_COLOR", "1"), ("CLICOLOR_FORCE", "1")],
+                ColorDepth::Ansi16,
+            ),
+            (
+                &[("TERM", "many"), ("NO_COLOR", "1"), ("CLICOLOR_FORCE", "0")],
+                ColorDepth::None,
+            ),
+        ] {
+            let var = |name: &str| {
+                vars.iter()
+                    .find(|(key, _)| *key == name)
+                    .map(|(_, value)| (*value).to_owned())
+            };
+            assert_eq!(color_depth(var, &root), depth, "{vars:?}");
+        }
+        drop(std::fs::remove_dir_all(root.path("/")));
+    }
+}
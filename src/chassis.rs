@@ -1,4 +1,4 @@
-use crate::{Icon, IconMode, virt};
+use crate::{Icon, IconMode, sysroot::Sysroot, virt};
 use std::{
     fs::File,
     io::{BufRead as _, BufReader},
@@ -6,7 +6,7 @@ use std::{
 };
 
 /// Chassis type, according to hostnamectl
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 #[non_exhaustive]
 pub enum Chassis {
     /// Desktops, nettops, etc
@@ -51,8 +51,42 @@ impl From<&str> for Chassis {
     }
 }
 
+impl Chassis {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Chassis::Desktop => "desktop",
+            Chassis::Server => "server",
+            Chassis::Laptop => "laptop",
+            Chassis::Convertible => "convertible",
+            Chassis::Tablet => "tablet",
+            Chassis::Handset => "handset",
+            Chassis::Watch => "watch",
+            Chassis::Embedded => "embedded",
+            Chassis::Virtual => "vm",
+            Chassis::Container => "container",
+            Chassis::Unknown => "unknown",
+        }
+    }
+}
+
 impl Icon for Chassis {
-    fn icon(&self, mode: IconMode) -> &'static str {
+    fn key(&self) -> &'static str {
+        match self {
+            Self::Desktop => "chassis.desktop",
+            Self::Server => "chassis.server",
+            Self::Laptop => "chassis.laptop",
+            Self::Convertible => "chassis.convertible",
+            Self::Tablet => "chassis.tablet",
+            Self::Handset => "chassis.handset",
+            Self::Watch => "chassis.watch",
+            Self::Embedded => "chassis.embedded",
+            Self::Virtual => "chassis.virtual",
+            Self::Container => "chassis.container",
+            Self::Unknown => "chassis.unknown",
+        }
+    }
+
+    fn builtin(&self, mode: IconMode) -> &'static str {
         use IconMode::*;
         match self {
             Self::Desktop => match mode {
@@ -108,18 +142,28 @@ impl Chassis {
     ///
     /// Containered and virtual environments are likely to be misdetected. You can try overriding
     /// this via `/etc/machine-info` or `hostnamectl set-chassis`...
-    pub fn get() -> Chassis {
-        None.or_else(Chassis::try_machine_info)
-            .or_else(Chassis::try_container)
-            .or_else(Chassis::try_udev)
-            .or_else(Chassis::try_virtualization)
-            .or_else(Chassis::try_dmi_type)
-            .or_else(Chassis::try_acpi_profile)
-            .or_else(Chassis::try_devtree_type)
-            .unwrap_or(Chassis::Unknown)
+    pub fn get(root: &Sysroot) -> Chassis {
+        Chassis::detect(root).0
     }
 
-    fn try_machine_info() -> Option<Chassis> {
+    /// Same as [`Chassis::get`], but also tells which source has decided
+    pub fn detect(root: &Sysroot) -> (Chassis, &'static str) {
+        let sources: [(&str, Source); 7] = [
+            ("/etc/machine-info", Chassis::try_machine_info),
+            ("container detection", Chassis::try_container),
+            ("udev database", Chassis::try_udev),
+            ("virtualization detection", Chassis::try_virtualization),
+            ("DMI chassis type", Chassis::try_dmi_type),
+            ("ACPI PM profile", Chassis::try_acpi_profile),
+            ("device tree", Chassis::try_devtree_type),
+        ];
+        sources
+            .into_iter()
+            .find_map(|(source, method)| Some((method(root)?, source)))
+            .unwrap_or((Chassis::Unknown, "nothing"))
+    }
+
+    fn try_machine_info(root: &Sysroot) -> Option<Chassis> {
         /*
         /etc/machine-info into lines
         find-map
@@ -131,7 +175,7 @@ impl Chassis {
         | unquote
         | chassis_str
         */
-        BufReader::new(File::open("/etc/machine-info").ok()?)
+        BufReader::new(File::open(root.path("/etc/machine-info")).ok()?)
             .lines()
             .find_map(|x| {
                 Some(Chassis::from(
@@ -145,7 +189,7 @@ impl Chassis {
             })
     }
 
-    fn try_udev() -> Option<Chassis> {
+    fn try_udev(root: &Sysroot) -> Option<Chassis> {
         /*
         sd-device /sys/class/dmi/id points to /run/udev/data/+dmi:id
         hours wasted on this: about three,
@@ -156,22 +200,27 @@ impl Chassis {
         I can't be 100% sure this code works as NO machines I have acceess to
           have any chassis-related information in this file
         */
-        BufReader::new(File::open("/run/udev/data/+dmi:id").ok()?)
+        BufReader::new(File::open(root.path("/run/udev/data/+dmi:id")).ok()?)
             .lines()
             .find_map(|x| Some(Chassis::from(x.ok()?.strip_prefix("E:ID_CHASSIS=")?.trim())))
     }
 
-    fn try_virtualization() -> Option<Chassis> {
-        virt::detect_vm().unwrap().then_some(Chassis::Virtual)
+    // Failed detection decides `Unknown`, as in systemd
+    fn try_virtualization(root: &Sysroot) -> Option<Chassis> {
+        match virt::detect_vm(root) {
+            Ok(virt) => virt.filter(|virt| virt.is_vm()).map(|_| Chassis::Virtual),
+            Err(_) => Some(Chassis::Unknown),
+        }
     }
 
-    fn try_container() -> Option<Chassis> {
-        virt::detect_container()
-            .unwrap()
-            .then_some(Chassis::Container)
+    fn try_container(root: &Sysroot) -> Option<Chassis> {
+        match virt::detect_container(root) {
+            Ok(virt) => virt.map(|_| Chassis::Container),
+            Err(_) => Some(Chassis::Unknown),
+        }
     }
 
-    fn try_dmi_type() -> Option<Chassis> {
+    fn try_dmi_type(root: &Sysroot) -> Option<Chassis> {
         /*
         /sys/class/dmi/id/chassis_type as u32 in hex
         3, 4, 6, 7, D, 23, 24 -> desktop
@@ -182,19 +231,21 @@ impl Chassis {
         1F, 20 -> convertible
         21, 22 -> embedded
         */
-        Some(match read_single_u32("/sys/class/dmi/id/chassis_type")? {
-            0x03 | 0x04 | 0x06 | 0x07 | 0x0d | 0x23 | 0x24 => Chassis::Desktop,
-            0x08 | 0x09 | 0x0a | 0x0e => Chassis::Laptop,
-            0x0b => Chassis::Handset,
-            0x11 | 0x1c | 0x1d => Chassis::Server,
-            0x1e => Chassis::Tablet,
-            0x1f | 0x20 => Chassis::Convertible,
-            0x21 | 0x22 => Chassis::Embedded,
-            _ => Chassis::Unknown,
-        })
+        Some(
+            match read_single_u32(&root.path("/sys/class/dmi/id/chassis_type"))? {
+                0x03 | 0x04 | 0x06 | 0x07 | 0x0d | 0x23 | 0x24 => Chassis::Desktop,
+                0x08 | 0x09 | 0x0a | 0x0e => Chassis::Laptop,
+                0x0b => Chassis::Handset,
+                0x11 | 0x1c | 0x1d => Chassis::Server,
+                0x1e => Chassis::Tablet,
+                0x1f | 0x20 => Chassis::Convertible,
+                0x21 | 0x22 => Chassis::Embedded,
+                _ => Chassis::Unknown,
+            },
+        )
     }
 
-    fn try_acpi_profile() -> Option<Chassis> {
+    fn try_acpi_profile(root: &Sysroot) -> Option<Chassis> {
         /*
         /sys/firmware/acpi/pm_profile as u32 in dec
         1, 3, 6 -> desktop
@@ -202,27 +253,32 @@ impl Chassis {
         4, 5, 7 -> server
         8 -> tablet
         */
-        Some(match read_single_u32("/sys/firmware/acpi/pm_profile")? {
-            1 | 3 | 6 => Chassis::Desktop,
-            2 => Chassis::Laptop,
-            4 | 5 | 7 => Chassis::Server,
-            8 => Chassis::Tablet,
-            _ => Chassis::Unknown,
-        })
+        Some(
+            match read_single_u32(&root.path("/sys/firmware/acpi/pm_profile"))? {
+                1 | 3 | 6 => Chassis::Desktop,
+                2 => Chassis::Laptop,
+                4 | 5 | 7 => Chassis::Server,
+                8 => Chassis::Tablet,
+                _ => Chassis::Unknown,
+            },
+        )
     }
 
-    fn try_devtree_type() -> Option<Chassis> {
+    fn try_devtree_type(root: &Sysroot) -> Option<Chassis> {
         /*
         /proc/device-tree/chassis-type as chassis_str
+        device tree strings are NUL-terminated
         */
         Some(Chassis::from(
-            std::fs::read_to_string("/proc/device-tree/chassis-type")
+            std::fs::read_to_string(root.path("/proc/device-tree/chassis-type"))
                 .ok()?
-                .as_str(),
+                .trim_end_matches('\0'),
         ))
     }
 }
 
+type Source = fn(&Sysroot) -> Option<Chassis>;
+
 fn read_single_u32<T: AsRef<Path> + ?Sized>(path: &T) -> Option<u32> {
     std::fs::read_to_string(path)
         .ok()?
@@ -230,3 +286,503 @@ fn read_single_u32<T: AsRef<Path> + ?Sized>(path: &T) -> Option<u32> {
         .parse::<u32>()
         .ok()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Chassis;
+    use crate::{
+        sysroot::{self, Cpuid},
+        virt::{self, Virtualization},
+    };
+
+    struct Machine {
+        name: &'static str,
+        /// CPU vendor from CPUID, `None` for machines without CPUID
+        cpu_vendor: Option<&'static str>,
+        /// Hypervisor signature from CPUID, if the hypervisor bit is set
+        hypervisor: Option<&'static str>,
+        files: &'static [(&'static str, &'static str)],
+        chassis: Chassis,
+        virt: Option<Virtualization>,
+    }
+
+    const INTEL: Option<&str> = Some("GenuineIntel");
+    const AMD: Option<&str> = Some("AuthenticAMD");
+
+    const MACHINES: &[Machine] = &[
+        Machine {
+            name: "empty",
+            cpu_vendor: None,
+            hypervisor: None,
+            files: &[],
+            chassis: Chassis::Unknown,
+            virt: None,
+        },
+        Machine {
+            name: "kvm-guest",
+            cpu_vendor: INTEL,
+            hypervisor: Some("KVMKVMKVM"),
+            files: &[("sys/class/dmi/id/chassis_type", "1\n")],
+            chassis: Chassis::Virtual,
+            virt: Some(Virtualization::Kvm),
+        },
+        Machine {
+            name: "qemu-tcg",
+            cpu_vendor: INTEL,
+            hypervisor: Some("TCGTCGTCGTCG"),
+            files: &[],
+            chassis: Chassis::Virtual,
+            virt: Some(Virtualization::Qemu),
+        },
+        Machine {
+            name: "virtualbox-kvm-paravirt",
+            cpu_vendor: AMD,
+            hypervisor: Some("KVMKVMKVM"),
+            files: &[
+                ("sys/class/dmi/id/product_name", "VirtualBox\n"),
+                ("sys/class/dmi/id/sys_vendor", "innotek GmbH\n"),
+            ],
+            chassis: Chassis::Virtual,
+            virt: Some(Virtualization::Oracle),
+        },
+        Machine {
+            name: "kvm-with-foreign-dmi",
+            cpu_vendor: INTEL,
+            hypervisor: Some("KVMKVMKVM"),
+            files: &[("sys/class/dmi/id/sys_vendor", "VMware, Inc.\n")],
+            chassis: Chassis::Virtual,
+            virt: Some(Virtualization::Kvm),
+        },
+        Machine {
+            name: "unknown-hypervisor",
+            cpu_vendor: INTEL,
+            hypervisor: Some("NotAHypervsr"),
+            files: &[],
+            chassis: Chassis::Virtual,
+            virt: Some(Virtualization::VmOther),
+        },
+        Machine {
+            name: "unknown-hypervisor-qemu-dmi",
+            cpu_vendor: INTEL,
+            hypervisor: Some("NotAHypervsr"),
+            files: &[("sys/class/dmi/id/sys_vendor", "QEMU\n")],
+            chassis: Chassis::Virtual,
+            virt: Some(Virtualization::Qemu),
+        },
+        Machine {
+            name: "bare-metal-server",
+            cpu_vendor: INTEL,
+            hypervisor: None,
+            files: &[
+                ("sys/class/dmi/id/sys_vendor", "Dell Inc.\n"),
+                ("sys/class/dmi/id/chassis_type", "17\n"),
+            ],
+            chassis: Chassis::Server,
+            virt: None,
+        },
+        Machine {
+            name: "vmware-workstation",
+            cpu_vendor: INTEL,
+            hypervisor: Some("VMwareVMware"),
+            files: &[("sys/class/dmi/id/sys_vendor", "VMware, Inc.\n")],
+            chassis: Chassis::Virtual,
+            virt: Some(Virtualization::Vmware),
+        },
+        Machine {
+            name: "hyper-v",
+            cpu_vendor: INTEL,
+            hypervisor: Some("Microsoft Hv"),
+            files: &[("sys/class/dmi/id/chassis_type", "3\n")],
+            chassis: Chassis::Virtual,
+            virt: Some(Virtualization::Microsoft),
+        },
+        Machine {
+            name: "virtualbox-without-cpuid",
+            cpu_vendor: None,
+            hypervisor: None,
+            files: &[
+                ("sys/class/dmi/id/product_name", "VirtualBox\n"),
+                ("sys/class/dmi/id/sys_vendor", "innotek GmbH\n"),
+            ],
+            chassis: Chassis::Virtual,
+            virt: Some(Virtualization::Oracle),
+        },
+        Machine {
+            name: "qemu-dmi",
+            cpu_vendor: None,
+            hypervisor: None,
+            files: &[("sys/class/dmi/id/sys_vendor", "QEMU\n")],
+            chassis: Chassis::Virtual,
+            virt: Some(Virtualization::Qemu),
+        },
+        Machine {
+            name: "aws-nitro-graviton",
+            cpu_vendor: None,
+            hypervisor: None,
+            files: &[
+                ("sys/class/dmi/id/product_name", "m7g.large\n"),
+                ("sys/class/dmi/id/sys_vendor", "Amazon EC2\n"),
+                ("sys/class/dmi/id/chassis_type", "1\n"),
+            ],
+            chassis: Chassis::Virtual,
+            virt: Some(Virtualization::Amazon),
+        },
+        Machine {
+            name: "aws-metal-graviton",
+            cpu_vendor: None,
+            hypervisor: None,
+            files: &[
+                ("sys/class/dmi/id/product_name", "c6g.metal\n"),
+                ("sys/class/dmi/id/sys_vendor", "Amazon EC2\n"),
+                ("sys/firmware/acpi/pm_profile", "4\n"),
+            ],
+            chassis: Chassis::Server,
+            virt: None,
+        },
+        Machine {
+            name: "aws-metal-intel",
+            cpu_vendor: INTEL,
+            hypervisor: None,
+            files: &[
+                ("sys/class/dmi/id/product_name", "m5.metal\n"),
+                ("sys/class/dmi/id/sys_vendor", "Amazon EC2\n"),
+                ("sys/class/dmi/id/chassis_type", "17\n"),
+            ],
+            chassis: Chassis::Server,
+            virt: None,
+        },
+        Machine {
+            name: "smbios-vm-bit",
+            cpu_vendor: None,
+            hypervisor: None,
+            files: &[(
+                "sys/firmware/dmi/entries/0-0/raw",
+                "\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\x10",
+            )],
+            chassis: Chassis::Virtual,
+            virt: Some(Virtualization::VmOther),
+        },
+        Machine {
+            name: "xen-domu",
+            cpu_vendor: INTEL,
+            hypervisor: Some("XenVMMXenVMM"),
+            files: &[("proc/xen/", "")],
+            chassis: Chassis::Virtual,
+            virt: Some(Virtualization::Xen),
+        },
+        Machine {
+            name: "xen-pv-domu",
+            cpu_vendor: None,
+            hypervisor: None,
+            files: &[("proc/xen/capabilities", "")],
+            chassis: Chassis::Virtual,
+            virt: Some(Virtualization::Xen),
+        },
+        Machine {
+            name: "xen-dom0",
+            cpu_vendor: None,
+            hypervisor: None,
+            files: &[
+                ("proc/xen/capabilities", "control_d\n"),
+                ("sys/class/dmi/id/chassis_type", "17\n"),
+            ],
+            chassis: Chassis::Server,
+            virt: Some(Virtualization::XenDom0),
+        },
+        Machine {
+            name: "xen-dom0-features",
+            cpu_vendor: None,
+            hypervisor: None,
+            files: &[
+                ("proc/xen/", ""),
+                ("sys/hypervisor/properties/features", "00000805\n"),
+            ],
+            chassis: Chassis::Unknown,
+            virt: Some(Virtualization::XenDom0),
+        },
+        Machine {
+            name: "hypervisor-type",
+            cpu_vendor: None,
+            hypervisor: None,
+            files: &[("sys/hypervisor/type", "xen\n")],
+            chassis: Chassis::Virtual,
+            virt: Some(Virtualization::Xen),
+        },
+        Machine {
+            name: "uml",
+            cpu_vendor: None,
+            hypervisor: None,
+            files: &[(
+                "proc/cpuinfo",
+                "processor\t: 0\nvendor_id\t: User Mode Linux\n",
+            )],
+            chassis: Chassis::Virtual,
+            virt: Some(Virtualization::Uml),
+        },
+        Machine {
+            name: "zvm-guest",
+            cpu_vendor: None,
+            hypervisor: None,
+            files: &[(
+                "proc/sysinfo",
+                "Manufacturer:         IBM\nVM00 Control Program: z/VM    7.3.0\n",
+            )],
+            chassis: Chassis::Virtual,
+            virt: Some(Virtualization::Zvm),
+        },
+        Machine {
+            name: "s390-kvm-guest",
+            cpu_vendor: None,
+            hypervisor: None,
+            files: &[(
+                "proc/sysinfo",
+                "Manufacturer:         IBM\nVM00 Control Program: KVM/Linux\n",
+            )],
+            chassis: Chassis::Virtual,
+            virt: Some(Virtualization::Kvm),
+        },
+        Machine {
+            name: "s390-lpar",
+            cpu_vendor: None,
+            hypervisor: None,
+            files: &[(
+                "proc/sysinfo",
+                "Manufacturer:         IBM\nLPAR Name:            LP01\n",
+            )],
+            chassis: Chassis::Unknown,
+            virt: None,
+        },
+        Machine {
+            name: "arm-kvm-device-tree",
+            cpu_vendor: None,
+            hypervisor: None,
+            files: &[("proc/device-tree/hypervisor/compatible", "linux,kvm\0")],
+            chassis: Chassis::Virtual,
+            virt: Some(Virtualization::Kvm),
+        },
+        Machine {
+            name: "arm-qemu-fw-cfg",
+            cpu_vendor: None,
+            hypervisor: None,
+            files: &[
+                ("proc/device-tree/compatible", "linux,dummy-virt\0"),
+                ("proc/device-tree/fw-cfg@9020000/", ""),
+            ],
+            chassis: Chassis::Virtual,
+            virt: Some(Virtualization::Qemu),
+        },
+        Machine {
+            name: "powervm-lpar",
+            cpu_vendor: None,
+            hypervisor: None,
+            files: &[
+                ("proc/device-tree/ibm,partition-name", "lpar1\0"),
+                ("proc/device-tree/hmc-managed?", ""),
+            ],
+            chassis: Chassis::Virtual,
+            virt: Some(Virtualization::PowerVm),
+        },
+        Machine {
+            name: "qemu-pseries",
+            cpu_vendor: None,
+            hypervisor: None,
+            files: &[("proc/device-tree/compatible", "qemu,pseries\0")],
+            chassis: Chassis::Virtual,
+            virt: Some(Virtualization::Qemu),
+        },
+        Machine {
+            name: "raspberry-pi",
+            cpu_vendor: None,
+            hypervisor: None,
+            files: &[
+                (
+                    "proc/device-tree/compatible",
+                    "raspberrypi,4-model-b\0brcm,bcm2711\0",
+                ),
+                ("proc/device-tree/chassis-type", "embedded\0"),
+            ],
+            chassis: Chassis::Embedded,
+            virt: None,
+        },
+        Machine {
+            name: "pinephone",
+            cpu_vendor: None,
+            hypervisor: None,
+            files: &[
+                ("proc/device-tree/compatible", "pine64,pinephone\0"),
+                ("proc/device-tree/chassis-type", "handset\0"),
+            ],
+            chassis: Chassis::Handset,
+            virt: None,
+        },
+        Machine {
+            name: "thinkpad",
+            cpu_vendor: INTEL,
+            hypervisor: None,
+            files: &[
+                ("sys/class/dmi/id/sys_vendor", "LENOVO\n"),
+                ("sys/class/dmi/id/chassis_type", "10\n"),
+                ("sys/firmware/acpi/pm_profile", "2\n"),
+            ],
+            chassis: Chassis::Laptop,
+            virt: None,
+        },
+        Machine {
+            name: "desktop",
+            cpu_vendor: AMD,
+            hypervisor: None,
+            files: &[("sys/class/dmi/id/chassis_type", "3\n")],
+            chassis: Chassis::Desktop,
+            virt: None,
+        },
+        Machine {
+            name: "convertible",
+            cpu_vendor: INTEL,
+            hypervisor: None,
+            files: &[("sys/class/dmi/id/chassis_type", "31\n")],
+            chassis: Chassis::Convertible,
+            virt: None,
+        },
+        Machine {
+            name: "tablet",
+            cpu_vendor: INTEL,
+            hypervisor: None,
+            files: &[("sys/class/dmi/id/chassis_type", "30\n")],
+            chassis: Chassis::Tablet,
+            virt: None,
+        },
+        Machine {
+            name: "acpi-only",
+            cpu_vendor: AMD,
+            hypervisor: None,
+            files: &[("sys/firmware/acpi/pm_profile", "1\n")],
+            chassis: Chassis::Desktop,
+            virt: None,
+        },
+        Machine {
+            name: "machine-info-override",
+            cpu_vendor: INTEL,
+            hypervisor: Some("KVMKVMKVM"),
+            files: &[("etc/machine-info", "PRETTY_HOSTNAME=Watch\nCHASSIS=watch\n")],
+            chassis: Chassis::Watch,
+            virt: Some(Virtualization::Kvm),
+        },
+        Machine {
+            name: "udev-chassis",
+            cpu_vendor: INTEL,
+            hypervisor: None,
+            files: &[
+                ("run/udev/data/+dmi:id", "E:ID_CHASSIS=handset\n"),
+                ("sys/class/dmi/id/chassis_type", "10\n"),
+            ],
+            chassis: Chassis::Handset,
+            virt: None,
+        },
+        Machine {
+            name: "docker",
+            cpu_vendor: INTEL,
+            hypervisor: None,
+            files: &[(".dockerenv", "")],
+            chassis: Chassis::Container,
+            virt: Some(Virtualization::Docker),
+        },
+        Machine {
+            name: "podman",
+            cpu_vendor: INTEL,
+            hypervisor: None,
+            files: &[("run/.containerenv", "engine=\"podman-4.9.3\"\n")],
+            chassis: Chassis::Container,
+            virt: Some(Virtualization::Podman),
+        },
+        Machine {
+            name: "systemd-nspawn",
+            cpu_vendor: AMD,
+            hypervisor: None,
+            files: &[("run/systemd/container", "systemd-nspawn\n")],
+            chassis: Chassis::Container,
+            virt: Some(Virtualization::SystemdNspawn),
+        },
+        Machine {
+            name: "lxc",
+            cpu_vendor: AMD,
+            hypervisor: None,
+            files: &[("proc/1/environ", "PATH=/usr/bin\0container=lxc\0")],
+            chassis: Chassis::Container,
+            virt: Some(Virtualization::Lxc),
+        },
+        Machine {
+            name: "openvz",
+            cpu_vendor: INTEL,
+            hypervisor: None,
+            files: &[("proc/vz/", "")],
+            chassis: Chassis::Container,
+            virt: Some(Virtualization::OpenVz),
+        },
+        Machine {
+            name: "flatpak",
+            cpu_vendor: INTEL,
+            hypervisor: None,
+            files: &[(".flatpak-info", "[Application]\n")],
+            chassis: Chassis::Container,
+            virt: Some(Virtualization::Flatpak),
+        },
+        Machine {
+            name: "wsl2",
+            cpu_vendor: INTEL,
+            hypervisor: Some("Microsoft Hv"),
+            files: &[(
+                "proc/sys/kernel/osrelease",
+                "5.15.153.1-microsoft-standard-WSL2\n",
+            )],
+            chassis: Chassis::Container,
+            virt: Some(Virtualization::Wsl),
+        },
+        Machine {
+            name: "proot",
+            cpu_vendor: None,
+            hypervisor: None,
+            files: &[
+                ("proc/self/status", "Name:\tbash\nTracerPid:\t42\n"),
+                ("proc/42/comm", "proot\n"),
+                ("proc/device-tree/chassis-type", "handset\0"),
+            ],
+            chassis: Chassis::Container,
+            virt: Some(Virtualization::Proot),
+        },
+        Machine {
+            name: "unreadable-hypervisor-type",
+            cpu_vendor: None,
+            hypervisor: None,
+            files: &[
+                ("sys/hypervisor/type/", ""),
+                ("sys/class/dmi/id/chassis_type", "10\n"),
+            ],
+            chassis: Chassis::Unknown,
+            virt: None,
+        },
+    ];
+
+    #[test]
+    fn known_machines() {
+        for machine in MACHINES {
+            let cpuid = machine.cpu_vendor.map(|vendor| Cpuid {
+                vendor: vendor.to_owned(),
+                hypervisor: machine.hypervisor.map(ToOwned::to_owned),
+            });
+            let root = sysroot::fixture(machine.name, cpuid, machine.files);
+            assert_eq!(
+                Chassis::get(&root),
+                machine.chassis,
+                "chassis of {}",
+                machine.name
+            );
+            assert_eq!(
+                virt::detect(&root).ok().flatten(),
+                machine.virt,
+                "virtualization of {}",
+                machine.name
+            );
+            drop(std::fs::remove_dir_all(root.path("/")));
+        }
+    }
+}
@@ -0,0 +1,268 @@
+//! Small subset of TOML: `[table]` headers, `key = value` pairs with strings, integers and
+//! booleans, and `#` comments. Enough for themes, and no dependency is needed
+
+use anyhow::{Context as _, Result, bail};
+use std::collections::HashSet;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Scalar {
+    String(String),
+    Integer(i64),
+    Bool(bool),
+}
+
+impl Scalar {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+/// One `key = value` line, with the table it is in (empty for top-level keys)
+#[derive(Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub table: String,
+    pub key: String,
+    pub value: Scalar,
+    pub line: usize,
+}
+
+/// Parses `text`, rejecting keys set twice in the same table and tables opened twice, as TOML does
+pub fn parse(text: &str) -> Result<Vec<Entry>> {
+    let mut table = String::new();
+    let mut tables = HashSet::new();
+    let mut entries: Vec<Entry> = vec![];
+    for (idx, raw) in text.lines().enumerate() {
+        let line = idx + 1;
+        let content = strip_comment(raw).trim();
+        if content.is_empty() {
+            continue;
+        }
+        if let Some(header) = content.strip_prefix('[') {
+            let Some(name) = header.strip_suffix(']') else {
+                bail!("line {line}: unterminated table header");
+            };
+            table = unquote(name.trim()).with_context(|| format!("line {line}"))?;
+            if !tables.insert(table.clone()) {
+                bail!("line {line}: table {table:?} is defined twice");
+            }
+            continue;
+        }
+        let Some((key, value)) = content.split_once('=') else {
+            bail!("line {line}: expected `key = value`");
+        };
+        let key = unquote(key.trim()).with_context(|| format!("line {line}"))?;
+        if let Some(first) = entries
+            .iter()
+            .find(|entry| entry.table == table && entry.key == key)
+        {
+            bail!(
+                "line {line}: key {key:?} is already set on line {}",
+                first.line
+            );
+        }
+        entries.push(Entry {
+            table: table.clone(),
+            key,
+            value: scalar(value.trim()).with_context(|| format!("line {line}"))?,
+            line,
+        });
+    }
+    Ok(entries)
+}
+
+/// Cuts `#` comment which is not inside a string
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (idx, c) in line.char_indices() {
+        match (quote, c) {
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            }
+            (Some(open), _) if c == open && !escaped => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '#') => return &line[..idx],
+            _ => {}
+        }
+        escaped = false;
+    }
+    line
+}
+
+/// Bare or quoted key
+fn unquote(key: &str) -> Result<String> {
+    if key.starts_with(['"', '\'']) {
+        return match scalar(key)? {
+            Scalar::String(s) => Ok(s),
+            _ => unreachable!(),
+        };
+    }
+    if key.is_empty()
+        || !key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+    {
+        bail!("bad key {key:?}");
+    }
+    Ok(key.to_owned())
+}
+
+fn scalar(value: &str) -> Result<Scalar> {
+    if let Some(literal) = value.strip_prefix('\'') {
+        let Some(literal) = literal.strip_suffix('\'') else {
+            bail!("unterminated string");
+        };
+        if literal.contains('\'') {
+            bail!("quote inside literal string");
+        }
+        return Ok(Scalar::String(literal.to_owned()));
+    }
+    if let Some(basic) = value.strip_prefix('"') {
+        let Some(basic) = basic.strip_suffix('"') else {
+            bail!("unterminated string");
+        };
+        return unescape(basic).map(Scalar::String);
+    }
+    match value {
+        "true" => return Ok(Scalar::Bool(true)),
+        "false" => return Ok(Scalar::Bool(false)),
+        _ => {}
+    }
+    value
+        .replace('_', "")
+        .parse()
+        .map(Scalar::Integer)
+        .with_context(|| format!("bad value {value:?}"))
+}
+
+fn unescape(s: &str) -> Result<String> {
+    let mut res = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '"' {
+            bail!("unescaped quote");
+        }
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => res.push('\n'),
+            Some('t') => res.push('\t'),
+            Some('\\') => res.push('\\'),
+            Some('"') => res.push('"'),
+            Some(kind @ ('u' | 'U')) => {
+                let len = if kind == 'u' { 4 } else { 8 };
+                let code: String = chars.by_ref().take(len).collect();
+                let decoded = u32::from_str_radix(&code, 16)
+                    .ok()
+                    .filter(|_| code.len() == len)
+                    .and_then(char::from_u32)
+                    .with_context(|| format!("bad escape \\{kind}{code}"))?;
+                res.push(decoded);
+            }
+            Some(other) => bail!("bad escape \\{other}"),
+            None => bail!("unterminated string"),
+        }
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Entry, Scalar, parse};
+
+    #[test]
+    fn parses_values() {
+        let text = r##"
+# comment
+base = "solarized"  # trailing comment
+hash = "#fff"
+literal = 'C:\path "quoted" # not a comment'
+escapes = "tab\tnewline\n\"quote\" back\\slash \u00e9\U0001F600"
+width = 1_000
+negative = -2
+bold = true
+"quoted key" = false
+
+[git.dirty]
+color = "red"
+[ 'literal table' ]
+color = 'blue'
+"##;
+        let entry = |table: &str, key: &str, value, line| Entry {
+            table: table.to_owned(),
+            key: key.to_owned(),
+            value,
+            line,
+        };
+        let string = |s: &str| Scalar::String(s.to_owned());
+        assert_eq!(
+            parse(text).unwrap(),
+            [
+                entry("", "base", string("solarized"), 3),
+                entry("", "hash", string("#fff"), 4),
+                entry(
+                    "",
+                    "literal",
+                    string(r#"C:\path "quoted" # not a comment"#),
+                    5
+                ),
+                entry(
+                    "",
+                    "escapes",
+                    string("tab\tnewline\n\"quote\" back\\slash \u{e9}\u{1f600}"),
+                    6,
+                ),
+                entry("", "width", Scalar::Integer(1000), 7),
+                entry("", "negative", Scalar::Integer(-2), 8),
+                entry("", "bold", Scalar::Bool(true), 9),
+                entry("", "quoted key", Scalar::Bool(false), 10),
+                entry("git.dirty", "color", string("red"), 13),
+                entry("literal table", "color", string("blue"), 15),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_errors() {
+        for (text, error) in [
+            ("[git.dirty", "line 1: unterminated table header"),
+            ("[]", "line 1: bad key \"\""),
+            ("[a]\n[b]\n[a]", "line 3: table \"a\" is defined twice"),
+            ("color", "line 1: expected `key = value`"),
+            ("bad key = 1", "line 1: bad key \"bad key\""),
+            (
+                "a = 1\nb = 2\na = 3",
+                "line 3: key \"a\" is already set on line 1",
+            ),
+            (
+                "[x]\na = 1\n[y]\na = 1\n[x]",
+                "line 5: table \"x\" is defined twice",
+            ),
+            ("a = \"open", "line 1: unterminated string"),
+            ("a = 'open", "line 1: unterminated string"),
+            ("a = \"escaped\\\"", "line 1: unterminated string"),
+            ("a = \"one\" \"two\"", "line 1: unescaped quote"),
+            ("a = 'one' 'two'", "line 1: quote inside literal string"),
+            ("a = \"\\q\"", "line 1: bad escape \\q"),
+            ("a = \"\\u12\"", "line 1: bad escape \\u12"),
+            ("a = \"\\uD800\"", "line 1: bad escape \\uD800"),
+            (
+                "a = yes",
+                "line 1: bad value \"yes\": invalid digit found in string",
+            ),
+            (
+                "a =",
+                "line 1: bad value \"\": cannot parse integer from empty string",
+            ),
+        ] {
+            let err = parse(text).expect_err(text);
+            assert_eq!(format!("{err:#}"), error, "{text:?}");
+        }
+    }
+}
@@ -0,0 +1,690 @@
+use crate::{Environment, file};
+use anyhow::{Context as _, Result, bail};
+use rustix::{
+    fd::OwnedFd,
+    fs::inotify::{self, CreateFlags, ReadFlags, WatchFlags},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::{OsStr, OsString},
+    io::{ErrorKind, Read as _, Write as _},
+    mem::MaybeUninit,
+    net::Shutdown,
+    os::unix::{
+        ffi::{OsStrExt as _, OsStringExt as _},
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+/// How long `run` waits for the daemon before computing everything by itself
+const TIMEOUT: Duration = Duration::from_millis(100);
+/// How long the daemon waits for the filesystem to settle down before refreshing the cache
+const DEBOUNCE: Duration = Duration::from_millis(50);
+/// Worktrees needing more watches than this are not cached at all
+const MAX_WATCHES: usize = 8192;
+/// Requests handled at once, the rest are not served
+const MAX_HANDLERS: usize = 16;
+/// Variables which change what git sees in the worktree. The daemon runs git without them, so
+/// shells which set them compute everything by themselves
+const GIT_VARS: &[&str] = &[
+    "GIT_DIR",
+    "GIT_WORK_TREE",
+    "GIT_COMMON_DIR",
+    "GIT_INDEX_FILE",
+    "GIT_OBJECT_DIRECTORY",
+    "GIT_ALTERNATE_OBJECT_DIRECTORIES",
+    "GIT_NAMESPACE",
+    "GIT_CEILING_DIRECTORIES",
+    "GIT_DISCOVERY_ACROSS_FILESYSTEM",
+    "GIT_CONFIG",
+    "GIT_CONFIG_GLOBAL",
+    "GIT_CONFIG_SYSTEM",
+    "GIT_CONFIG_NOSYSTEM",
+    "GIT_CONFIG_COUNT",
+    "GIT_CONFIG_PARAMETERS",
+    "GIT_REPLACE_REF_BASE",
+    "GIT_NO_REPLACE_OBJECTS",
+    "GIT_GRAFT_FILE",
+    "GIT_SHALLOW_FILE",
+];
+
+fn socket_path(runtime_dir: &OsStr) -> PathBuf {
+    Path::new(runtime_dir).join("statusline.sock")
+}
+
+/// Socket of the daemon, if the shell asked for one with `PS1_DAEMON` and sees the same
+/// repositories as the daemon does
+pub fn socket(environ: &Environment) -> Option<PathBuf> {
+    environ
+        .var("PS1_DAEMON")
+        .filter(|value| !value.is_empty())?;
+    if GIT_VARS.iter().any(|name| environ.var_os(name).is_some()) {
+        return None;
+    }
+    Some(socket_path(environ.var_os("XDG_RUNTIME_DIR")?))
+}
+
+/// Asks the daemon at `socket` for `git -C <tree> <args>` output, if the daemon is running,
+/// responsive and serves this worktree
+pub fn query(socket: &Path, tree: &Path, args: &[&str]) -> Option<Vec<u8>> {
+    let mut stream = UnixStream::connect(socket).ok()?;
+    stream.set_read_timeout(Some(TIMEOUT)).ok()?;
+    stream.set_write_timeout(Some(TIMEOUT)).ok()?;
+
+    let mut request = tree.as_os_str().as_bytes().to_vec();
+    for arg in args {
+        request.push(0);
+        request.extend_from_slice(arg.as_bytes());
+    }
+    stream.write_all(&request).ok()?;
+    stream.shutdown(Shutdown::Write).ok()?;
+
+    let mut response = vec![];
+    stream.read_to_end(&mut response).ok()?;
+    // Daemon answers with `NOT_SERVED` if it refused the request or git failed
+    response
+        .split_first()
+        .and_then(|(&ok, output)| (ok == SERVED).then(|| output.to_vec()))
+}
+
+/// First byte of the response, followed by git output
+const SERVED: u8 = 1;
+/// The only byte of the response when the client has to run git by itself
+const NOT_SERVED: u8 = 0;
+
+/// Whether `args` are one of the git commands `run` asks for, nothing else is run by the daemon
+fn allowed(args: &[String]) -> bool {
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        ["status", "--porcelain=2"] => true,
+        ["rev-list", "--count", "--left-right", range] => range
+            .split_once("...")
+            .is_some_and(|(left, right)| is_ref(left) && is_ref(right)),
+        _ => false,
+    }
+}
+
+/// Whether `name` is a branch name which can not be taken as an option or revision expression
+fn is_ref(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(['-', '.', '/'])
+        && !name.ends_with(['.', '/'])
+        && !name.contains("..")
+        && !name.contains("@{")
+        && !name
+            .chars()
+            .any(|c| c.is_control() || c.is_whitespace() || "~^:?*[\\".contains(c))
+}
+
+/// Splits request into worktree and git arguments, refusing anything but absolute paths to
+/// worktrees and allowed commands
+fn parse_request(request: &[u8]) -> Option<(PathBuf, Vec<String>)> {
+    let mut fields = request.split(|&c| c == 0);
+    let tree = PathBuf::from(OsString::from_vec(fields.next()?.to_vec()));
+    let args = fields
+        .map(|arg| String::from_utf8(arg.to_vec()).ok())
+        .collect::<Option<Vec<_>>>()?;
+    (tree.is_absolute() && tree.join(".git").exists() && allowed(&args)).then_some((tree, args))
+}
+
+#[derive(Default)]
+struct Tree {
+    /// Cached outputs, by git arguments
+    outputs: HashMap<Vec<String>, Vec<u8>>,
+    /// Arguments which were cached before the last change and should be recomputed
+    stale: HashSet<Vec<String>>,
+    /// Bumped on every change in the worktree
+    generation: u64,
+    /// Whether the worktree is being watched, unwatched worktrees are never cached
+    watched: bool,
+    /// Whether the worktree has too many directories to be watched, such worktrees are not served
+    unwatchable: bool,
+    /// Whether the refresher thread is already running for this worktree
+    refreshing: bool,
+}
+
+#[derive(Default)]
+struct Cache {
+    trees: HashMap<PathBuf, Tree>,
+    /// Watch descriptors, pointing to worktree and the watched directory itself
+    watches: HashMap<i32, (PathBuf, PathBuf)>,
+}
+
+struct Daemon {
+    cache: Mutex<Cache>,
+    inotify: OwnedFd,
+    /// Requests being handled
+    handlers: AtomicUsize,
+}
+
+/// Place of a request among [`MAX_HANDLERS`], freed when dropped
+struct Slot(Arc<Daemon>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.handlers.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Serves git command outputs for `run` from memory, refreshing them on filesystem changes
+pub fn serve() -> Result<()> {
+    let path =
+        socket_path(&std::env::var_os("XDG_RUNTIME_DIR").context("XDG_RUNTIME_DIR is not set")?);
+    let listener = bind(&path)?;
+    // Do not die together with the terminal which started us
+    let _session = rustix::process::setsid();
+
+    let daemon = Arc::new(Daemon::new()?);
+
+    std::thread::spawn({
+        let daemon = Arc::clone(&daemon);
+        move || daemon.watch()
+    });
+
+    for stream in listener.incoming() {
+        let Ok(mut stream) = stream else { continue };
+        let Some(slot) = Daemon::admit(&daemon) else {
+            // Client runs git by itself then, and should not wait for it
+            drop(stream.write_all(&[NOT_SERVED]));
+            continue;
+        };
+        std::thread::spawn(move || slot.0.handle(stream));
+    }
+    Ok(())
+}
+
+fn bind(path: &Path) -> Result<UnixListener> {
+    match UnixListener::bind(path) {
+        Err(e) if e.kind() == ErrorKind::AddrInUse => {
+            if UnixStream::connect(path).is_ok() {
+                bail!("Daemon is already running at {}", path.display());
+            }
+            // Nobody listens there, the previous daemon must have been killed
+            std::fs::remove_file(path)?;
+            Ok(UnixListener::bind(path)?)
+        }
+        res => Ok(res?),
+    }
+}
+
+/// `git -C <tree>` seeing the worktree as shells without [`GIT_VARS`] do
+fn git(tree: &Path) -> Command {
+    let mut git = Command::new("git");
+    git.arg("-C").arg(tree);
+    for name in GIT_VARS {
+        git.env_remove(name);
+    }
+    git
+}
+
+fn run_git(tree: &Path, args: &[String]) -> Result<Vec<u8>> {
+    let output = git(tree)
+        .args(args)
+        // Refreshing index from here would trigger another refresh, and so on
+        .env("GIT_OPTIONAL_LOCKS", "0")
+        .output()?;
+    if !output.status.success() {
+        bail!("git exited with {}", output.status);
+    }
+    Ok(output.stdout)
+}
+
+impl Daemon {
+    fn new() -> Result<Self> {
+        Ok(Daemon {
+            cache: Mutex::default(),
+            inotify: inotify::init(CreateFlags::CLOEXEC)?,
+            handlers: AtomicUsize::new(0),
+        })
+    }
+
+    /// Takes a slot for a new request, if there is a free one
+    fn admit(this: &Arc<Self>) -> Option<Slot> {
+        this.handlers
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                (count < MAX_HANDLERS).then_some(count + 1)
+            })
+            .ok()?;
+        Some(Slot(Arc::clone(this)))
+    }
+
+    fn handle(&self, mut stream: UnixStream) -> Result<()> {
+        // Clients which neither finish the request nor read the answer do not keep threads
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        let mut request = vec![];
+        stream.read_to_end(&mut request)?;
+        match self.answer(&request) {
+            Some(output) => {
+                stream.write_all(&[SERVED])?;
+                stream.write_all(&output)?;
+            }
+            None => stream.write_all(&[NOT_SERVED])?,
+        }
+        Ok(())
+    }
+
+    fn answer(&self, request: &[u8]) -> Option<Vec<u8>> {
+        let (tree, args) = parse_request(request)?;
+        let (cached, generation, watched) = {
+            let mut cache = self.cache.lock().unwrap();
+            let entry = cache.trees.entry(tree.clone()).or_default();
+            if entry.unwatchable {
+                return None;
+            }
+            (
+                entry.outputs.get(&args).cloned(),
+                entry.generation,
+                entry.watched,
+            )
+        };
+        if cached.is_some() {
+            return cached;
+        }
+
+        // Watch first, so that changes made while git is running are not lost
+        if !watched && !self.watch_tree(&tree) {
+            let mut cache = self.cache.lock().unwrap();
+            cache.trees.entry(tree).or_default().unwatchable = true;
+            return None;
+        }
+        let output = run_git(&tree, &args).ok()?;
+        self.store(tree, args, generation, output.clone());
+        Some(output)
+    }
+
+    /// Caches `output` unless the worktree has changed since `generation`, while git was running
+    fn store(&self, tree: PathBuf, args: Vec<String>, generation: u64, output: Vec<u8>) {
+        let mut cache = self.cache.lock().unwrap();
+        let entry = cache.trees.entry(tree).or_default();
+        entry.watched = true;
+        if entry.generation == generation {
+            entry.outputs.insert(args, output);
+        }
+    }
+
+    /// Watches worktree directories along with refs and state files in git directory
+    fn watch_tree(&self, tree: &Path) -> bool {
+        let Some(git_dir) = file::git_dir(tree) else {
+            return false;
+        };
+
+        // Changes in ignored directories like `target` do not affect status at all
+        let ignored: HashSet<PathBuf> = git(tree)
+            .args(["ls-files", "--others", "--ignored", "--exclude-standard"])
+            .arg("--directory")
+            .output()
+            .map(|out| {
+                out.stdout
+                    .split(|&c| c == b'\n')
+                    .filter_map(|line| line.strip_suffix(b"/"))
+                    .map(|dir| tree.join(OsString::from_vec(dir.to_vec())))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let mut dirs = vec![git_dir.clone()];
+        collect_dirs(&git_dir.join("refs"), &HashSet::new(), &mut dirs);
+        collect_dirs(tree, &ignored, &mut dirs);
+        if dirs.len() > MAX_WATCHES {
+            return false;
+        }
+
+        let mut cache = self.cache.lock().unwrap();
+        for dir in dirs {
+            self.add_watch(&mut cache, tree, dir);
+        }
+        true
+    }
+
+    fn add_watch(&self, cache: &mut Cache, tree: &Path, dir: PathBuf) {
+        let flags = WatchFlags::MODIFY
+            | WatchFlags::ATTRIB
+            | WatchFlags::CREATE
+            | WatchFlags::DELETE
+            | WatchFlags::MOVE
+            | WatchFlags::ONLYDIR;
+        if let Ok(wd) = inotify::add_watch(&self.inotify, &dir, flags) {
+            cache.watches.insert(wd, (tree.to_path_buf(), dir));
+        }
+    }
+
+    fn watch(self: Arc<Self>) -> Result<()> {
+        let mut buf = [MaybeUninit::uninit(); 4096];
+        let mut reader = inotify::Reader::new(&self.inotify, &mut buf);
+        loop {
+            let event = reader.next()?;
+            let mut cache = self.cache.lock().unwrap();
+
+            if event.events().contains(ReadFlags::QUEUE_OVERFLOW) {
+                let trees: Vec<_> = cache.trees.keys().cloned().collect();
+                for tree in trees {
+                    self.invalidate(&mut cache, &tree);
+                }
+                continue;
+            }
+
+            let Some((tree, dir)) = cache.watches.get(&event.wd()).cloned() else {
+                continue;
+            };
+            if event.events().contains(ReadFlags::IGNORED) {
+                cache.watches.remove(&event.wd());
+                continue;
+            }
+            if event
+                .events()
+                .contains(ReadFlags::ISDIR | ReadFlags::CREATE)
+                && let Some(name) = event.file_name()
+            {
+                let mut dirs = vec![];
+                collect_dirs(
+                    &dir.join(OsString::from_vec(name.to_bytes().to_vec())),
+                    &HashSet::new(),
+                    &mut dirs,
+                );
+                for new_dir in dirs {
+                    self.add_watch(&mut cache, &tree, new_dir);
+                }
+            }
+            self.invalidate(&mut cache, &tree);
+        }
+    }
+
+    fn invalidate(self: &Arc<Self>, cache: &mut Cache, tree: &Path) {
+        let Some(entry) = cache.trees.get_mut(tree) else {
+            return;
+        };
+        entry.generation += 1;
+        entry
+            .stale
+            .extend(entry.outputs.drain().map(|(args, _)| args));
+        if !entry.refreshing && !entry.stale.is_empty() {
+            entry.refreshing = true;
+            let daemon = Arc::clone(self);
+            let tree = tree.to_path_buf();
+            std::thread::spawn(move || daemon.refresh(&tree));
+        }
+    }
+
+    /// Recomputes outputs which were requested before, so that they are warm on the next prompt
+    fn refresh(&self, tree: &Path) {
+        loop {
+            std::thread::sleep(DEBOUNCE);
+            let Some((generation, stale)) = self
+                .cache
+                .lock()
+                .unwrap()
+                .trees
+                .get(tree)
+                .map(|entry| (entry.generation, entry.stale.clone()))
+            else {
+                return;
+            };
+
+            let outputs: Vec<_> = stale
+                .into_iter()
+                .filter_map(|args| Some((run_git(tree, &args).ok()?, args)))
+                .collect();
+
+            let mut cache = self.cache.lock().unwrap();
+            let Some(entry) = cache.trees.get_mut(tree) else {
+                return;
+            };
+            // Otherwise something has changed while git was running, so try again later
+            if entry.generation == generation {
+                for (output, args) in outputs {
+                    entry.stale.remove(&args);
+                    entry.outputs.insert(args, output);
+                }
+                entry.refreshing = false;
+                return;
+            }
+        }
+    }
+}
+
+fn collect_dirs(dir: &Path, ignored: &HashSet<PathBuf>, dirs: &mut Vec<PathBuf>) {
+    if dirs.len() > MAX_WATCHES || ignored.contains(dir) {
+        return;
+    }
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    dirs.push(dir.to_path_buf());
+    for entry in entries.map_while(Result::ok) {
+        if entry.file_type().is_ok_and(|kind| kind.is_dir()) && entry.file_name() != ".git" {
+            collect_dirs(&entry.path(), ignored, dirs);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sysroot;
+    use std::time::Instant;
+
+    const STATUS: [&str; 2] = ["status", "--porcelain=2"];
+
+    /// Empty repository with one untracked file
+    fn repo(name: &str) -> PathBuf {
+        let tree = sysroot::fixture(name, None, &[("tree/file", "")]).path("/tree");
+        let status = Command::new("git")
+            .args(["init", "-q"])
+            .arg(&tree)
+            .env("GIT_CONFIG_GLOBAL", "/dev/null")
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .status()
+            .expect("git should be installed");
+        assert!(status.success());
+        tree
+    }
+
+    fn request(tree: &Path, args: &[&str]) -> Vec<u8> {
+        let mut request = tree.as_os_str().as_bytes().to_vec();
+        for arg in args {
+            request.push(0);
+            request.extend_from_slice(arg.as_bytes());
+        }
+        request
+    }
+
+    fn ask(daemon: &Daemon, request: &[u8]) -> Vec<u8> {
+        let (mut client, server) = UnixStream::pair().unwrap();
+        client.write_all(request).unwrap();
+        client.shutdown(Shutdown::Write).unwrap();
+        daemon.handle(server).unwrap();
+        let mut response = vec![];
+        client.read_to_end(&mut response).unwrap();
+        response
+    }
+
+    fn cached(daemon: &Daemon, tree: &Path) -> Option<Vec<u8>> {
+        let args: Vec<_> = STATUS.map(ToOwned::to_owned).into();
+        daemon.cache.lock().unwrap().trees[tree]
+            .outputs
+            .get(&args)
+            .cloned()
+    }
+
+    #[test]
+    fn allows_known_commands() {
+        for (args, ok) in [
+            (&["status", "--porcelain=2"][..], true),
+            (&["status"], false),
+            (&["status", "--porcelain=2", "--ignored"], false),
+            (&["config", "--list"], false),
+            (
+                &["rev-list", "--count", "--left-right", "main...origin/main"],
+                true,
+            ),
+            (
+                &[
+                    "rev-list",
+                    "--count",
+                    "--left-right",
+                    "feature/x...up/feature/x",
+                ],
+                true,
+            ),
+            (
+                &["rev-list", "--count", "--left-right", "main..origin/main"],
+                false,
+            ),
+            (
+                &["rev-list", "--count", "--left-right", "--all...main"],
+                false,
+            ),
+            (
+                &[
+                    "rev-list",
+                    "--count",
+                    "--left-right",
+                    "main...origin/main~1",
+                ],
+                false,
+            ),
+            (
+                &["rev-list", "--count", "--left-right", "main...@{upstream}"],
+                false,
+            ),
+            (
+                &["rev-list", "--count", "--left-right", "main...a b"],
+                false,
+            ),
+            (&["rev-list", "--count", "--left-right", "...main"], false),
+            (&["rev-list", "--count", "--left-right"], false),
+        ] {
+            let args: Vec<_> = args.iter().map(|&arg| arg.to_owned()).collect();
+            assert_eq!(allowed(&args), ok, "{args:?}");
+        }
+    }
+
+    #[test]
+    fn frames_requests() {
+        let tree = repo("daemon-framing");
+        let daemon = Daemon::new().unwrap();
+
+        let response = ask(&daemon, &request(&tree, &STATUS));
+        assert_eq!(response[0], SERVED);
+        assert_eq!(&response[1..], b"? file\n");
+        assert_eq!(cached(&daemon, &tree).as_deref(), Some(&b"? file\n"[..]));
+
+        for refused in [
+            request(&tree, &["config", "--list"]),
+            request(&tree, &["status"]),
+            request(Path::new("tree"), &STATUS),
+            request(&tree.join(".git"), &STATUS),
+            [request(&tree, &["status", "--porcelain="]), vec![0xff]].concat(),
+            vec![],
+        ] {
+            assert_eq!(ask(&daemon, &refused), [NOT_SERVED], "{refused:?}");
+        }
+
+        // Worktrees which were too big to watch once are refused at once
+        daemon
+            .cache
+            .lock()
+            .unwrap()
+            .trees
+            .get_mut(&tree)
+            .unwrap()
+            .unwatchable = true;
+        assert_eq!(ask(&daemon, &request(&tree, &STATUS)), [NOT_SERVED]);
+    }
+
+    #[test]
+    fn refreshes_on_changes() {
+        let tree = repo("daemon-refresh");
+        let daemon = Arc::new(Daemon::new().unwrap());
+        std::thread::spawn({
+            let daemon = Arc::clone(&daemon);
+            move || daemon.watch()
+        });
+
+        assert_eq!(ask(&daemon, &request(&tree, &STATUS))[0], SERVED);
+        std::fs::write(tree.join("new"), "").unwrap();
+
+        let start = Instant::now();
+        while cached(&daemon, &tree).as_deref() != Some(b"? file\n? new\n") {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "status was not refreshed"
+            );
+            std::thread::sleep(DEBOUNCE);
+        }
+        assert_ne!(daemon.cache.lock().unwrap().trees[&tree].generation, 0);
+    }
+
+    #[test]
+    fn drops_outdated_outputs() {
+        let tree = repo("daemon-race");
+        let daemon = Arc::new(Daemon::new().unwrap());
+        let args: Vec<_> = STATUS.map(ToOwned::to_owned).into();
+
+        daemon
+            .cache
+            .lock()
+            .unwrap()
+            .trees
+            .insert(tree.clone(), Tree::default());
+        // Worktree changes while git is running
+        daemon.invalidate(&mut daemon.cache.lock().unwrap(), &tree);
+        daemon.store(tree.clone(), args.clone(), 0, b"racing".to_vec());
+        assert_eq!(cached(&daemon, &tree), None);
+
+        daemon.store(tree.clone(), args, 1, b"new".to_vec());
+        assert_eq!(cached(&daemon, &tree).as_deref(), Some(&b"new"[..]));
+    }
+
+    #[test]
+    fn queries_only_running_daemon() {
+        let missing = sysroot::fixture("daemon-query", None, &[]).path("/statusline.sock");
+        assert_eq!(query(&missing, Path::new("/"), &STATUS), None);
+
+        let mut environ = Environment::at(PathBuf::from("/"), PathBuf::from("/"));
+        environ.vars = [("XDG_RUNTIME_DIR".into(), "/run/user/1000".into())].into();
+        assert_eq!(socket(&environ), None);
+        environ.vars.insert("PS1_DAEMON".into(), "1".into());
+        assert_eq!(
+            socket(&environ).as_deref(),
+            Some(Path::new("/run/user/1000/statusline.sock"))
+        );
+        // Daemon would answer for another repository or configuration
+        environ
+            .vars
+            .insert("GIT_DIR".into(), "/srv/repo.git".into());
+        assert_eq!(socket(&environ), None);
+    }
+
+    #[test]
+    fn drops_stuck_clients() {
+        let daemon = Daemon::new().unwrap();
+        let (_client, server) = UnixStream::pair().unwrap();
+        let start = Instant::now();
+        assert!(daemon.handle(server).is_err());
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn limits_handlers() {
+        let daemon = Arc::new(Daemon::new().unwrap());
+        let mut slots: Vec<_> = (0..MAX_HANDLERS)
+            .map(|_| Daemon::admit(&daemon).unwrap())
+            .collect();
+        assert!(Daemon::admit(&daemon).is_none());
+        slots.pop();
+        assert!(Daemon::admit(&daemon).is_some());
+    }
+}
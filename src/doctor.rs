@@ -0,0 +1,358 @@
+use crate::{
+    Chassis, Color, IconMode, IconSet, Style, WithStyle as _, capability,
+    json::Value,
+    palette::Palette,
+    style::ColorDepth,
+    sysroot::{Cpuid, Sysroot},
+    theme::{Background, Theme},
+    virt,
+    workgroup::WorkgroupKey,
+};
+use std::{fmt::Write as _, os::unix::fs::PermissionsExt as _, process::Command};
+
+#[derive(Clone, Copy)]
+enum Status {
+    Ok,
+    Warn,
+    Fail,
+    Info,
+}
+
+impl Status {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Warn => "warn",
+            Self::Fail => "fail",
+            Self::Info => "info",
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            Self::Ok => Color::GREEN,
+            Self::Warn => Color::YELLOW,
+            Self::Fail => Color::RED,
+            Self::Info => Color::CYAN,
+        }
+    }
+}
+
+pub struct Check {
+    name: &'static str,
+    status: Status,
+    message: String,
+}
+
+impl Check {
+    fn new(name: &'static str, status: Status, message: impl Into<String>) -> Self {
+        Check {
+            name,
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+/// Checks installation of statusline and tells what it has detected about this machine
+pub fn diagnose(exec: &str) -> Vec<Check> {
+    let root = Sysroot::host();
+    vec![
+        check_path(exec),
+        check_env(exec),
+        check_glyphs(),
+        check_icons(),
+        check_colors(&root),
+        check_theme(),
+        check_palette(),
+        check_git(),
+        check_workgroup(),
+        check_chassis(&root),
+        check_virt(&root),
+    ]
+}
+
+fn check_path(exec: &str) -> Check {
+    let name = "path";
+    let found = std::env::var_os("PATH").and_then(|path| {
+        std::env::split_paths(&path)
+            .map(|dir| dir.join("statusline"))
+            .find(|candidate| crate::file::points_to_file(candidate))
+    });
+    let Some(found) = found else {
+        return Check::new(
+            name,
+            Status::Warn,
+            "statusline is not in PATH, `env` script has to use full path",
+        );
+    };
+    if std::fs::canonicalize(&found).ok() == std::fs::canonicalize(exec).ok() {
+        Check::new(name, Status::Ok, format!("found at {}", found.display()))
+    } else {
+        Check::new(
+            name,
+            Status::Warn,
+            format!("{} is another binary than {exec}", found.display()),
+        )
+    }
+}
+
+fn check_env(exec: &str) -> Check {
+    let name = "env";
+    let Ok(stamp) = std::env::var("_SL_STAMP") else {
+        return Check::new(
+            name,
+            Status::Fail,
+            format!("env script is not sourced, add `source <(\"{exec}\" env)` to ~/.bashrc"),
+        );
+    };
+    if crate::env_script(exec).contains(&format!("_SL_STAMP={stamp}\n")) {
+        Check::new(name, Status::Ok, "env script is sourced and up to date")
+    } else {
+        Check::new(
+            name,
+            Status::Warn,
+            format!("env script is outdated, run `source <(\"{exec}\" env)` or restart shell"),
+        )
+    }
+}
+
+fn check_glyphs() -> Check {
+    let name = "glyphs";
+    let term = std::env::var("TERM").unwrap_or_default();
+    let mode = std::env::var("PS1_MODE").unwrap_or_default();
+    // Same as in `env` script
+    let text = matches!(term.as_str(), "linux" | "screen") || term.starts_with("tmux-");
+    match term.as_str() {
+        "dumb" => Check::new(name, Status::Warn, "TERM=dumb, statusline is disabled"),
+        _ if text || mode == "text" => Check::new(
+            name,
+            Status::Info,
+            format!("text mode is used with TERM={term}, nerd font glyphs are not needed"),
+        ),
+        _ => Check::new(
+            name,
+            Status::Info,
+            format!(
+                "can not tell if nerd font is installed, if you see boxes instead of \u{f07b} \
+                 here, set PS1_MODE=text. Glyphs are taken as {} wide by PS1_NERD_WIDTH",
+                match std::env::var("PS1_NERD_WIDTH").as_deref() {
+                    Ok("2") => "two columns",
+                    _ => "one column",
+                }
+            ),
+        ),
+    }
+}
+
+fn check_icons() -> Check {
+    let name = "icons";
+    let mode = std::env::var("PS1_MODE").unwrap_or_default();
+    if mode.is_empty() || IconMode::builtin(&mode).is_some() {
+        return Check::new(name, Status::Info, "built-in icons are used");
+    }
+    match IconSet::load(&mode, |var| std::env::var(var).ok()) {
+        Ok(_) => Check::new(name, Status::Ok, format!("icon set {mode} is used")),
+        Err(err) => Check::new(
+            name,
+            Status::Fail,
+            format!(
+                "{err:#}, default icons are used instead. Set PS1_MODE to text, icons, minimal, \
+                 a path or a name of file in ~/.config/statusline/icons"
+            ),
+        ),
+    }
+}
+
+fn check_colors(root: &Sysroot) -> Check {
+    let name = "colors";
+    let depth = capability::color_depth(|var| std::env::var(var).ok(), root);
+    let term = std::env::var("TERM").unwrap_or_default();
+    match depth {
+        ColorDepth::True => Check::new(name, Status::Ok, "terminal supports truecolor"),
+        ColorDepth::Ansi256 | ColorDepth::Ansi16 => Check::new(
+            name,
+            Status::Info,
+            format!(
+                "TERM={term} supports {}, host and branch colors are approximated. Set \
+                 COLORTERM=truecolor if terminal supports more",
+                depth.as_str()
+            ),
+        ),
+        ColorDepth::None => Check::new(
+            name,
+            Status::Warn,
+            "colors are disabled by NO_COLOR or TERM, set CLICOLOR_FORCE=1 to force them",
+        ),
+    }
+}
+
+fn check_git() -> Check {
+    let name = "git";
+    match Command::new("git").arg("--version").output() {
+        Ok(out) if out.status.success() => Check::new(
+            name,
+            Status::Ok,
+            String::from_utf8_lossy(&out.stdout).trim().to_owned(),
+        ),
+        Ok(out) => Check::new(
+            name,
+            Status::Fail,
+            format!("git exited with {}", out.status),
+        ),
+        Err(e) => Check::new(
+            name,
+            Status::Fail,
+            format!("git is not available ({e}), repository status will not be shown"),
+        ),
+    }
+}
+
+fn check_workgroup() -> Check {
+    let name = "workgroup";
+    let home = std::env::var_os("HOME");
+    let path = WorkgroupKey::path(home.as_deref());
+    let mode = match std::fs::metadata(&path) {
+        Ok(meta) => meta.permissions().mode(),
+        Err(_) => {
+            return Check::new(
+                name,
+                Status::Info,
+                "no workgroup key, ssh chain is shown only from SSH_CONNECTION",
+            );
+        }
+    };
+    if mode & 0o077 != 0 {
+        return Check::new(
+            name,
+            Status::Warn,
+            format!(
+                "{} is accessible by others (mode {:o})",
+                path.display(),
+                mode & 0o777
+            ),
+        );
+    }
+    match WorkgroupKey::load(home.as_deref()) {
+        Ok(_) => Check::new(name, Status::Ok, format!("{} is loaded", path.display())),
+        Err(e) => Check::new(name, Status::Fail, format!("{}: {e:#}", path.display())),
+    }
+}
+
+fn check_chassis(root: &Sysroot) -> Check {
+    let (chassis, source) = Chassis::detect(root);
+    Check::new(
+        "chassis",
+        Status::Info,
+        format!("{} according to {source}", chassis.as_str()),
+    )
+}
+
+fn check_theme() -> Check {
+    let name = "theme";
+    let theme = std::env::var("PS1_THEME").unwrap_or_default();
+    let background = std::env::var("PS1_BACKGROUND").ok();
+    match Theme::load(Some(&theme), Background::from_name(background.as_deref())) {
+        Ok(_) if theme.is_empty() => Check::new(
+            name,
+            Status::Info,
+            format!(
+                "theme follows terminal background, which is {}",
+                background.as_deref().unwrap_or("not detected")
+            ),
+        ),
+        Ok(_) => Check::new(name, Status::Ok, format!("theme {theme} is used")),
+        Err(err) => {
+            let builtin: Vec<_> = Theme::builtin_names().collect();
+            Check::new(
+                name,
+                Status::Fail,
+                format!(
+                    "{err:#}, default theme is used instead. Set PS1_THEME to a path or one of {}",
+                    builtin.join(", ")
+                ),
+            )
+        }
+    }
+}
+
+fn check_palette() -> Check {
+    let name = "palette";
+    match Palette::load(|var| std::env::var(var).ok()) {
+        Ok(_) => Check::new(
+            name,
+            Status::Info,
+            format!(
+                "names are colored with {} palette",
+                std::env::var("PS1_HASH_PALETTE")
+                    .ok()
+                    .filter(|palette| !palette.is_empty())
+                    .as_deref()
+                    .unwrap_or("hsv")
+            ),
+        ),
+        Err(err) => {
+            let known: Vec<_> = Palette::names().collect();
+            Check::new(
+                name,
+                Status::Fail,
+                format!(
+                    "{err:#}, default palette is used instead. PS1_HASH_PALETTE may be one of {}",
+                    known.join(", ")
+                ),
+            )
+        }
+    }
+}
+
+fn check_virt(root: &Sysroot) -> Check {
+    let describe = |res: anyhow::Result<Option<virt::Virtualization>>| match res {
+        Ok(Some(virt)) => virt.as_str().to_owned(),
+        Ok(None) => "none".to_owned(),
+        Err(e) => format!("error ({e:#})"),
+    };
+    let cpuid = match root.cpuid() {
+        Some(Cpuid {
+            vendor,
+            hypervisor: Some(hypervisor),
+        }) => format!("{vendor} under {hypervisor:?}"),
+        Some(Cpuid { vendor, .. }) => format!("{vendor} without hypervisor"),
+        None => "not available".to_owned(),
+    };
+    Check::new(
+        "virt",
+        Status::Info,
+        format!(
+            "virtual machine: {}, container: {}, CPUID: {cpuid}",
+            describe(virt::detect_vm(root)),
+            describe(virt::detect_container(root)),
+        ),
+    )
+}
+
+pub fn human(checks: &[Check]) -> String {
+    let mut res = String::new();
+    for check in checks {
+        res.with_style(check.status.color(), Style::BOLD, |f| {
+            write!(f, "[{:^4}]", check.status.as_str())
+        })
+        .unwrap();
+        writeln!(res, " {:<10} {}", check.name, check.message).unwrap();
+    }
+    res
+}
+
+pub fn json(checks: &[Check]) -> Value {
+    Value::Array(
+        checks
+            .iter()
+            .map(|check| {
+                Value::Object(vec![
+                    ("check", check.name.into()),
+                    ("status", check.status.as_str().into()),
+                    ("message", check.message.as_str().into()),
+                ])
+            })
+            .collect(),
+    )
+}
@@ -32,9 +32,7 @@ pub fn exists<P: AsRef<Path>>(path: P) -> bool {
 }
 
 pub fn points_to_file<P: AsRef<Path>>(path: P) -> bool {
-    std::fs::metadata(path)
-        .map(|meta| meta.is_file())
-        .unwrap_or(false)
+    std::fs::metadata(path).is_ok_and(|meta| meta.is_file())
 }
 
 pub fn exists_that<P: AsRef<Path>, F>(path: P, mut f: F) -> std::io::Result<bool>
@@ -56,3 +54,30 @@ pub fn upfind<P: AsRef<Path>>(start: P, filename: &str) -> Option<PathBuf> {
         .map(|path| path.join(filename))
         .find(|path| exists(path))
 }
+
+/// Finds git directory of the worktree, following `.git` files of linked worktrees
+pub fn git_dir(tree: &Path) -> Option<PathBuf> {
+    let dotgit = tree.join(".git");
+    if dotgit.is_file() {
+        Some(
+            tree.join(
+                std::fs::read_to_string(&dotgit)
+                    .ok()?
+                    .strip_prefix("gitdir: ")?
+                    .trim_end_matches(['\r', '\n']),
+            ),
+        )
+    } else {
+        Some(dotgit)
+    }
+}
+
+/// Branch checked out in the worktree, or abbreviated commit if `HEAD` is detached
+pub fn git_branch(tree: &Path) -> Option<String> {
+    let head = std::fs::read_to_string(git_dir(tree)?.join("HEAD")).ok()?;
+    let head = head.trim();
+    Some(match head.strip_prefix("ref: refs/heads/") {
+        Some(branch) => branch.to_owned(),
+        None => head.get(..7)?.to_owned(),
+    })
+}
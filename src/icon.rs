@@ -1,4 +1,10 @@
-use std::fmt::{Display, Formatter};
+use crate::config::{self, Scalar};
+use anyhow::{Context as _, Result, bail};
+use std::{
+    cell::Cell,
+    fmt::{Display, Formatter},
+    path::PathBuf,
+};
 
 /// Icon mode configurer
 #[non_exhaustive]
@@ -12,10 +18,159 @@ pub enum IconMode {
     MinimalIcons,
 }
 
+impl IconMode {
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(Self::Text),
+            "icons" => Some(Self::Icons),
+            "minimal" => Some(Self::MinimalIcons),
+            _ => None,
+        }
+    }
+}
+
 /// Associated icon getter, which respects icon mode
 pub trait Icon {
-    /// Returns associated icon with respect to icon mode
-    fn icon(&self, mode: IconMode) -> &'static str;
+    /// Name of the icon in icon set files, like `git.ahead`
+    fn key(&self) -> &'static str;
+
+    /// Returns associated icon of the built-in icon mode
+    fn builtin(&self, mode: IconMode) -> &'static str;
+
+    /// Returns associated icon from the icon set in use, or of the icon mode if the set has none
+    fn icon(&self, mode: IconMode) -> &'static str {
+        custom(self.key()).unwrap_or_else(|| self.builtin(mode))
+    }
+}
+
+/// Every key an icon set file may have
+pub const KEYS: &[&str] = &[
+    "chassis.desktop",
+    "chassis.server",
+    "chassis.laptop",
+    "chassis.convertible",
+    "chassis.tablet",
+    "chassis.handset",
+    "chassis.watch",
+    "chassis.embedded",
+    "chassis.virtual",
+    "chassis.container",
+    "chassis.unknown",
+    "user",
+    "container",
+    "rootless",
+    "ssh",
+    "nix",
+    "venv",
+    "mail",
+    "elapsed",
+    "git.branch",
+    "git.unborn",
+    "git.commit",
+    "git.unknown",
+    "git.bisecting",
+    "git.reverting",
+    "git.cherry-picking",
+    "git.merging",
+    "git.rebasing",
+    "git.ahead",
+    "git.behind",
+    "git.stashes",
+    "git.conflict",
+    "git.staged",
+    "git.dirty",
+    "git.untracked",
+    "workdir.writeable",
+    "workdir.readonly",
+    "workdir.deleted",
+    "workdir.moved",
+    "workdir.forbidden",
+    "return.ok",
+    "return.fail",
+    "return.signal",
+    "return.unavailable",
+    "shell.root",
+    "shell.user",
+    "ellipsis",
+    "continuation",
+];
+
+/// Icons read from a file, which replace the built-in ones of `base` mode
+pub struct IconSet {
+    base: IconMode,
+    icons: Vec<(&'static str, String)>,
+}
+
+impl IconSet {
+    /// Icon set file: top-level `base` names the built-in mode to take missing icons from, and
+    /// keys like `ahead` in `[git]` table, or top-level `ssh`, set icons
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut set = IconSet {
+            base: IconMode::Icons,
+            icons: vec![],
+        };
+        for entry in config::parse(text)? {
+            let key = if entry.table.is_empty() {
+                entry.key
+            } else {
+                format!("{}.{}", entry.table, entry.key)
+            };
+            let Scalar::String(value) = entry.value else {
+                bail!("line {}: expected string", entry.line);
+            };
+            if key == "base" {
+                set.base = IconMode::builtin(&value)
+                    .with_context(|| format!("line {}: no icon mode {value:?}", entry.line))?;
+                continue;
+            }
+            let Some(&known) = KEYS.iter().find(|&&known| known == key) else {
+                bail!("line {}: unknown icon {key:?}", entry.line);
+            };
+            set.icons.push((known, value));
+        }
+        Ok(set)
+    }
+
+    /// `PS1_MODE` which is not a built-in mode is either a path to icon set file, or a name of
+    /// one in `~/.config/statusline/icons`
+    pub fn load(name: &str, var: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let path = if name.contains('/') {
+            PathBuf::from(name)
+        } else {
+            let set = |key: &str| var(key).filter(|value| !value.is_empty());
+            let config = set("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .or_else(|| set("HOME").map(|home| PathBuf::from(home).join(".config")))
+                .context("no config directory")?;
+            config.join(format!("statusline/icons/{name}.toml"))
+        };
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("can not read icon set {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("in icon set {}", path.display()))
+    }
+
+    /// Built-in mode which icons missing from the set are taken from
+    pub fn base(&self) -> IconMode {
+        self.base
+    }
+}
+
+thread_local! {
+    static ICONS: Cell<Option<&'static IconSet>> = const { Cell::new(None) };
+}
+
+/// Sets icon set used to render on this thread, it lives as long as the process does
+pub fn set(icons: IconSet) {
+    ICONS.set(Some(Box::leak(Box::new(icons))));
+}
+
+/// Icon under `key` in the icon set in use
+fn custom(key: &str) -> Option<&'static str> {
+    let icons = ICONS.get()?;
+    icons
+        .icons
+        .iter()
+        .find_map(|(known, icon)| (*known == key).then_some(icon.as_str()))
 }
 
 /// Pretty formatter with respect to selected icon mode
@@ -24,6 +179,22 @@ pub trait Pretty {
     fn pretty(&self, f: &mut Formatter<'_>, mode: IconMode) -> std::fmt::Result;
 }
 
+/// Marks text which was left out to fit the line
+pub(crate) fn ellipsis(mode: IconMode) -> &'static str {
+    custom("ellipsis").unwrap_or(match mode {
+        IconMode::Text => "...",
+        IconMode::Icons | IconMode::MinimalIcons => "…",
+    })
+}
+
+/// Starts the line of working directory, when it does not fit next to other blocks
+pub(crate) fn continuation(mode: IconMode) -> &'static str {
+    custom("continuation").unwrap_or(match mode {
+        IconMode::Text => ">",
+        IconMode::Icons | IconMode::MinimalIcons => "\u{f105}",
+    })
+}
+
 pub(crate) fn display(pretty: &dyn Pretty, mode: IconMode) -> impl Display {
     // XXX: Use `std::fmt::from_fn` when 1.93 hits
     struct DisplayHelper<'a>(&'a dyn Pretty, IconMode);
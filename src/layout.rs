@@ -0,0 +1,130 @@
+//! Fitting lines into narrow terminals, by compacting and dropping less important blocks
+
+use crate::{Block, IconMode};
+
+/// Makes `groups` shown on one line, with `extra` columns taken by separators, fit into `width`.
+/// Blocks are visited from the lowest priority: each is compacted as far as it goes, then dropped
+/// if the line is still too long
+pub fn fit(groups: &mut [&mut Vec<Box<dyn Block>>], width: usize, extra: usize, mode: IconMode) {
+    let fits = |line: &[&mut Vec<Box<dyn Block>>]| {
+        let used: usize = line
+            .iter()
+            .map(|group| crate::pretty_width(group, mode))
+            .sum();
+        used + extra <= width
+    };
+
+    let mut order: Vec<(u8, usize, usize)> = groups
+        .iter()
+        .enumerate()
+        .flat_map(|(group, blocks)| {
+            blocks
+                .iter()
+                .enumerate()
+                .map(move |(idx, block)| (block.priority(), group, idx))
+        })
+        .collect();
+    order.sort_by_key(|&(priority, ..)| priority);
+
+    let mut next = 0;
+    while !fits(groups) {
+        let Some(&(priority, group, idx)) = order.get(next) else {
+            return;
+        };
+        if groups[group][idx].compact() {
+            continue;
+        }
+        next += 1;
+        if priority == u8::MAX {
+            continue;
+        }
+        groups[group].remove(idx);
+        for (_, other_group, other_idx) in &mut order[next..] {
+            if *other_group == group && *other_idx > idx {
+                *other_idx -= 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fit;
+    use crate::{Block, Environment, IconMode, Pretty, json::Value};
+
+    /// Shows the last of its forms, compacting drops one
+    struct Stub(u8, Vec<&'static str>);
+
+    impl Block for Stub {
+        fn new(_: &Environment) -> Option<Self> {
+            None
+        }
+        fn json(&self) -> Value {
+            Value::Null
+        }
+        fn priority(&self) -> u8 {
+            self.0
+        }
+        fn compact(&mut self) -> bool {
+            self.1.len() > 1 && self.1.pop().is_some()
+        }
+    }
+
+    impl Pretty for Stub {
+        fn pretty(&self, f: &mut std::fmt::Formatter<'_>, _: IconMode) -> std::fmt::Result {
+            f.write_str(self.1.last().unwrap())
+        }
+    }
+
+    fn line(blocks: &[(u8, &[&'static str])]) -> Vec<Box<dyn Block>> {
+        blocks
+            .iter()
+            .map(|(priority, forms)| {
+                let forms = forms.iter().rev().copied().collect();
+                Box::new(Stub(*priority, forms)) as Box<dyn Block>
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fits_line() {
+        let aaaa: &[_] = &["aaaa"];
+        let bbbb: &[_] = &["bbbb"];
+        let cccc: &[_] = &["cccc"];
+        for (blocks, width, expected) in [
+            // Fits as is
+            (vec![(10, aaaa), (50, bbbb)], 9, "aaaa bbbb"),
+            // Lowest priority is dropped first
+            (vec![(10, aaaa), (50, bbbb), (20, cccc)], 9, "bbbb cccc"),
+            (vec![(10, aaaa), (20, bbbb), (50, cccc)], 4, "cccc"),
+            // Compacted before dropped, and dropped if it is still too long
+            (vec![(10, &["aaaa", "aa", "a"]), (50, bbbb)], 7, "aa bbbb"),
+            (vec![(10, &["aaaa", "a"]), (50, bbbb)], 6, "a bbbb"),
+            (vec![(10, &["aaaa", "a"]), (50, bbbb)], 4, "bbbb"),
+            (vec![(10, &["aaaa", "a"]), (50, &["bbbb", "b"])], 3, "b"),
+            // Never dropped, and nothing is done once nothing can be shorter
+            (vec![(u8::MAX, aaaa), (50, bbbb)], 2, "aaaa"),
+            (
+                vec![(u8::MAX, &["aaaa", "aa"]), (u8::MAX, bbbb)],
+                1,
+                "aa bbbb",
+            ),
+        ] {
+            let mut blocks = line(&blocks);
+            fit(&mut [&mut blocks], width, 0, IconMode::Text);
+            assert_eq!(crate::pretty(&blocks, IconMode::Text), expected, "{width}");
+        }
+    }
+
+    #[test]
+    fn fits_groups() {
+        let mut left = line(&[(10, &["aaaa"]), (50, &["bbbb"])]);
+        let mut right = line(&[(20, &["cccc"])]);
+        fit(&mut [&mut left, &mut right], 12, 3, IconMode::Text);
+        assert_eq!(crate::pretty(&left, IconMode::Text), "bbbb");
+        assert_eq!(crate::pretty(&right, IconMode::Text), "cccc");
+        fit(&mut [&mut left, &mut right], 7, 3, IconMode::Text);
+        assert_eq!(crate::pretty(&left, IconMode::Text), "bbbb");
+        assert!(right.is_empty());
+    }
+}
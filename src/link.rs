@@ -0,0 +1,150 @@
+//! Hyperlinks to directories and to web pages of git remotes, shown with OSC 8
+
+use crate::Environment;
+use std::{fmt::Write as _, os::unix::ffi::OsStrExt as _, path::Path};
+
+/// Links are shown unless `PS1_LINKS=0`, or the terminal is known to print them as text
+pub fn enabled(environ: &Environment) -> bool {
+    match environ.var("PS1_LINKS") {
+        Some("0") => false,
+        Some("1") => true,
+        _ => !environ
+            .var("TERM")
+            .is_some_and(|term| matches!(term, "linux" | "dumb") || term.starts_with("screen")),
+    }
+}
+
+/// Percent-encodes everything except unreserved characters and `/`
+fn encode(bytes: &[u8]) -> String {
+    let mut res = String::new();
+    for &byte in bytes {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+            res.push(char::from(byte));
+        } else {
+            write!(res, "%{byte:02X}").unwrap();
+        }
+    }
+    res
+}
+
+/// `file://host/path`, so that the terminal can tell remote directories from local ones
+pub fn file(host: &str, path: &Path) -> String {
+    format!("file://{host}{}", encode(path.as_os_str().as_bytes()))
+}
+
+/// Forges differ in where branches and commits are
+#[derive(Clone, Copy)]
+enum Forge {
+    GitHub,
+    GitLab,
+    /// Gitea, Forgejo and Codeberg
+    Gitea,
+}
+
+impl Forge {
+    fn of(host: &str) -> Option<Self> {
+        if host.contains("github") {
+            Some(Self::GitHub)
+        } else if host.contains("gitlab") {
+            Some(Self::GitLab)
+        } else if host == "codeberg.org" || host.contains("gitea") || host.contains("forgejo") {
+            Some(Self::Gitea)
+        } else {
+            None
+        }
+    }
+}
+
+/// Web page of a repository
+pub struct Repo {
+    forge: Forge,
+    /// `https://host/owner/name`
+    base: String,
+}
+
+impl Repo {
+    /// Web page of remote with `url` from `remote.<name>.url`, which is either a URL or
+    /// scp-like `user@host:owner/name.git`
+    pub fn parse(url: &str) -> Option<Self> {
+        let url = url.trim().trim_end_matches('/');
+        let url = url.strip_suffix(".git").unwrap_or(url);
+        let (scheme, authority, path) = if let Some((scheme, rest)) = url.split_once("://") {
+            let (authority, path) = rest.split_once('/')?;
+            match scheme {
+                "http" | "https" => (scheme, authority, path),
+                // Ports of ssh and git protocols are not the ones of the web page
+                _ => ("https", authority.split(':').next()?, path),
+            }
+        } else {
+            let (authority, path) = url.split_once(':')?;
+            if authority.contains('/') {
+                return None;
+            }
+            ("https", authority, path.trim_start_matches('/'))
+        };
+        let host = authority.rsplit('@').next()?;
+        let forge = Forge::of(host.split(':').next()?)?;
+        if path.is_empty() {
+            return None;
+        }
+        Some(Repo {
+            forge,
+            base: format!("{scheme}://{host}/{path}"),
+        })
+    }
+
+    pub fn branch(&self, name: &str) -> String {
+        let name = encode(name.as_bytes());
+        match self.forge {
+            Forge::GitHub => format!("{}/tree/{name}", self.base),
+            Forge::GitLab => format!("{}/-/tree/{name}", self.base),
+            Forge::Gitea => format!("{}/src/branch/{name}", self.base),
+        }
+    }
+
+    pub fn commit(&self, id: &str) -> String {
+        match self.forge {
+            Forge::GitHub | Forge::Gitea => format!("{}/commit/{id}", self.base),
+            Forge::GitLab => format!("{}/-/commit/{id}", self.base),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Repo;
+
+    #[test]
+    fn parses_remotes() {
+        for (url, branch) in [
+            (
+                "git@github.com:alice/project.git",
+                Some("https://github.com/alice/project/tree/main"),
+            ),
+            (
+                "https://github.com/alice/project",
+                Some("https://github.com/alice/project/tree/main"),
+            ),
+            (
+                "ssh://git@gitlab.example.org:2222/group/sub/project.git",
+                Some("https://gitlab.example.org/group/sub/project/-/tree/main"),
+            ),
+            (
+                "https://token@codeberg.org/alice/project.git/",
+                Some("https://codeberg.org/alice/project/src/branch/main"),
+            ),
+            (
+                "http://gitea.local:3000/alice/project",
+                Some("http://gitea.local:3000/alice/project/src/branch/main"),
+            ),
+            ("git@example.org:alice/project.git", None),
+            ("/srv/git/project.git", None),
+        ] {
+            assert_eq!(
+                Repo::parse(url).map(|repo| repo.branch("main")).as_deref(),
+                branch,
+                "{url}"
+            );
+        }
+    }
+}
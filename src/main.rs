@@ -64,41 +64,63 @@
     clippy::new_ret_no_self
 )]
 
+mod bar;
 mod block;
+mod capability;
 mod chassis;
+mod config;
+mod daemon;
+mod doctor;
 mod file;
 mod icon;
+mod json;
+mod layout;
+mod link;
+mod palette;
+mod powerline;
+mod profile;
+mod render;
+#[cfg(test)]
+mod snapshot;
 mod style;
+mod sysroot;
+mod theme;
+mod title;
+mod tty;
 mod virt;
+mod width;
 mod workgroup;
 
 use crate::{
     block::{Block, create_blocks},
     chassis::Chassis,
-    icon::{Icon, IconMode, Pretty},
-    style::{Color, Style, WithStyle, horizontal_absolute},
+    icon::{Icon, IconMode, IconSet, Pretty},
+    json::Value,
+    palette::Palette,
+    powerline::Separator,
+    render::RenderBackend as _,
+    style::{Color, Style, WithStyle, right_aligned},
+    sysroot::Sysroot,
+    theme::{Background, Role, Theme},
+    virt::Virtualization,
     workgroup::{SshChain, WorkgroupKey},
 };
 use argh::FromArgs;
+use chrono::{DateTime, FixedOffset, Local};
 use pwd::Passwd;
 use rustix::{
     fd::{FromRawFd as _, OwnedFd},
     fs::{Mode, OFlags},
 };
-use std::{fmt::Write as _, io::Write as _, os::fd::AsRawFd as _, path::PathBuf, time::Duration};
-use unicode_width::UnicodeWidthStr as _;
-
-fn readline_width(s: &str) -> usize {
-    let mut res = s.width();
-    for (i, c) in s.bytes().enumerate() {
-        match c {
-            b'\x01' => res += i,
-            b'\x02' => res -= i + 1,
-            _ => {}
-        }
-    }
-    res
-}
+use std::{
+    collections::HashMap,
+    ffi::{OsStr, OsString},
+    fmt::Write as _,
+    io::Write as _,
+    os::fd::AsRawFd as _,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 #[derive(FromArgs)]
 /// statusline
@@ -116,6 +138,17 @@ enum Command {
     Chain(Chain),
     Run(Run),
     Env(Env),
+    Daemon(Daemon),
+    Bench(Bench),
+    Doctor(Doctor),
+    DetectVirt(DetectVirt),
+    Background(BackgroundQuery),
+    NerdWidth(NerdWidthQuery),
+    Title(Title),
+    Transient(Transient),
+    Render(Render),
+    Tmux(Tmux),
+    Bar(Bar),
 }
 
 #[derive(FromArgs)]
@@ -123,6 +156,157 @@ enum Command {
 /// print bash commands
 struct Env {}
 
+#[derive(FromArgs)]
+#[argh(subcommand, name = "daemon")]
+/// serve prompt data from memory
+struct Daemon {}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "bench")]
+/// time blocks, git calls and rendering in current directory
+struct Bench {
+    #[argh(option, default = "10")]
+    /// how many times to build the statusline
+    runs: usize,
+
+    #[argh(switch)]
+    /// print timings as JSON
+    json: bool,
+
+    #[argh(option)]
+    /// icon mode. `text` and `minimal` have special meaning
+    mode: Option<String>,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "doctor")]
+/// check installation and show what was detected
+struct Doctor {
+    #[argh(switch)]
+    /// print checks as JSON
+    json: bool,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "detect-virt")]
+/// print virtualization technology like systemd-detect-virt
+struct DetectVirt {
+    #[argh(switch)]
+    /// only detect virtual machines
+    vm: bool,
+
+    #[argh(switch)]
+    /// only detect containers
+    container: bool,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "background")]
+/// ask terminal whether its background is light or dark
+struct BackgroundQuery {
+    #[argh(option, default = "100")]
+    /// milliseconds to wait for the answer
+    timeout: u64,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "nerd-width")]
+/// ask terminal how many columns nerd font glyphs take
+struct NerdWidthQuery {
+    #[argh(option, default = "100")]
+    /// milliseconds to wait for the answer
+    timeout: u64,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "transient")]
+/// collapse the prompt which a command was just typed in
+struct Transient {
+    #[argh(option)]
+    /// return code shown in the prompt
+    return_code: Option<u8>,
+
+    #[argh(option)]
+    /// background jobs count shown in the prompt
+    jobs_count: Option<usize>,
+
+    #[argh(option)]
+    /// elapsed time shown in the prompt, in microseconds
+    elapsed_time: Option<u64>,
+
+    #[argh(option)]
+    /// icon mode. `text` and `minimal` have special meaning
+    mode: Option<String>,
+
+    #[argh(option, default = "String::new()")]
+    /// command typed in the prompt
+    command: String,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "title")]
+/// print escape sequence which sets terminal title from `PS1_TITLE`
+struct Title {
+    #[argh(option, default = "String::new()")]
+    /// command which is about to run
+    command: String,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "render")]
+/// print blocks in one line with chosen markup
+struct Render {
+    #[argh(option)]
+    /// markup: readline, zsh, ansi, plain, pango or tmux
+    backend: String,
+
+    #[argh(option)]
+    /// directory to show blocks for, current one by default
+    path: Option<PathBuf>,
+
+    #[argh(option, default = "LINE_BLOCKS.to_owned()")]
+    /// comma-separated blocks to show
+    blocks: String,
+
+    #[argh(option)]
+    /// icon mode, `PS1_MODE` by default
+    mode: Option<String>,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "tmux")]
+/// print blocks for tmux status line
+struct Tmux {
+    #[argh(option)]
+    /// directory to show blocks for, usually `#{pane_current_path}`
+    pane_path: PathBuf,
+
+    #[argh(option, default = "LINE_BLOCKS.to_owned()")]
+    /// comma-separated blocks to show
+    blocks: String,
+
+    #[argh(option)]
+    /// icon mode, `PS1_MODE` by default
+    mode: Option<String>,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "bar")]
+/// print blocks for i3bar or swaybar
+struct Bar {
+    #[argh(option)]
+    /// seconds between updates, prints only once if not set
+    interval: Option<u64>,
+
+    #[argh(option, default = "BAR_BLOCKS.to_owned()")]
+    /// comma-separated blocks to show
+    blocks: String,
+
+    #[argh(option)]
+    /// icon mode, `PS1_MODE` by default
+    mode: Option<String>,
+}
+
 #[derive(FromArgs)]
 #[argh(subcommand, name = "chain")]
 /// append this host to chain
@@ -139,10 +323,14 @@ struct WorkgroupCreate {}
 struct Colorize {
     #[argh(option)]
     /// what to colorize
-    what: String,
+    what: Option<String>,
+
+    #[argh(switch)]
+    /// colorize names from stdin, one per line, and tell which of them look alike
+    list: bool,
 }
 
-#[derive(FromArgs)]
+#[derive(FromArgs, Default)]
 #[argh(subcommand, name = "run")]
 /// main statusline
 struct Run {
@@ -165,6 +353,14 @@ struct Run {
     #[argh(option)]
     /// icon mode. `text` and `minimal` have special meaning
     mode: Option<String>,
+
+    #[argh(option)]
+    /// output format. `json` prints data of every block instead of the statusline
+    format: Option<String>,
+
+    #[argh(option)]
+    /// only draw lines above the prompt again, after they were drawn for terminal this wide
+    redraw: Option<usize>,
 }
 
 /// Environment variables available to statusline
@@ -185,17 +381,41 @@ pub struct Environment {
     pub host: String,
     /// Current home: dir and username
     pub current_home: Option<(PathBuf, String)>,
+    /// Filesystem which system information is read from
+    pub sysroot: Sysroot,
+    /// Process working directory as seen by the kernel, `.` outside of tests
+    pub cwd: PathBuf,
+    /// Whether the shell is running as root
+    pub is_root: bool,
+    /// Time at which the statusline is built
+    pub now: DateTime<FixedOffset>,
+    /// Environment variables of the shell
+    pub vars: HashMap<OsString, OsString>,
 }
 
-impl From<Run> for Environment {
-    fn from(other: Run) -> Environment {
-        let ret_code = other.return_code;
-        let jobs_count = other.jobs_count;
-        let elapsed_time = other.elapsed_time.map(Duration::from_micros);
+impl Environment {
+    pub fn var_os(&self, name: &str) -> Option<&OsStr> {
+        self.vars.get(OsStr::new(name)).map(OsString::as_os_str)
+    }
 
-        let work_dir = std::env::current_dir()
-            .unwrap_or_else(|_| PathBuf::from(std::env::var("PWD").unwrap()));
+    pub fn var(&self, name: &str) -> Option<&str> {
+        self.var_os(name)?.to_str()
+    }
 
+    /// `git -C <tree>` seeing variables of the shell, which configure git too
+    pub fn git(&self, tree: &Path) -> std::process::Command {
+        let mut git = std::process::Command::new("git");
+        git.arg("-C").arg(tree).env_clear().envs(&self.vars);
+        git
+    }
+}
+
+impl Environment {
+    /// Environment of a shell in `work_dir` which has not run any command yet
+    ///
+    /// `cwd` is how the same directory is reachable by this process, which is `.` when running
+    /// inside the shell itself.
+    fn at(work_dir: PathBuf, cwd: PathBuf) -> Environment {
         let git_tree = file::upfind(&work_dir, ".git").map(|dg| dg.parent().unwrap().to_path_buf());
 
         // XXX: This probably does not work well under Termux
@@ -211,25 +431,46 @@ impl From<Run> for Environment {
         let current_home = file::find_current_home(&work_dir, &user);
 
         Environment {
-            ret_code,
-            jobs_count,
-            elapsed_time,
+            ret_code: None,
+            jobs_count: None,
+            elapsed_time: None,
             work_dir,
             git_tree,
             user,
             host,
             current_home,
+            sysroot: Sysroot::host(),
+            cwd,
+            is_root: rustix::process::getuid().is_root(),
+            now: Local::now().fixed_offset(),
+            vars: std::env::vars_os().collect(),
+        }
+    }
+}
+
+impl From<Run> for Environment {
+    fn from(other: Run) -> Environment {
+        let work_dir = std::env::current_dir()
+            .unwrap_or_else(|_| PathBuf::from(std::env::var("PWD").unwrap()));
+
+        Environment {
+            ret_code: other.return_code,
+            jobs_count: other.jobs_count,
+            elapsed_time: other.elapsed_time.map(Duration::from_micros),
+            ..Environment::at(work_dir, PathBuf::from("."))
         }
     }
 }
 
 fn main() {
-    let exec = std::fs::read_link("/proc/self/exe")
-        .map(|pb| pb.to_string_lossy().into_owned())
-        .unwrap_or("<executable>".to_owned());
+    let exec = std::fs::read_link("/proc/self/exe").map_or("<executable>".to_owned(), |pb| {
+        pb.to_string_lossy().into_owned()
+    });
 
     let args: Arguments = argh::from_env();
 
+    load_colors();
+
     let Some(command) = args.command else {
         let ver = env!("CARGO_PKG_VERSION");
         let apply_me = format!("source <(\"{exec}\" env)");
@@ -245,21 +486,22 @@ fn main() {
     };
 
     match command {
-        Command::Colorize(Colorize { what }) => {
-            let mut res = String::new();
-            res.with_style(Color::of(&what), Style::BOLD, |f| write!(f, "{what}"))
-                .unwrap();
-            println!("{res}");
-        }
+        Command::Colorize(colorize) => run_colorize(colorize),
         Command::WorkgroupCreate(_) => {
-            WorkgroupKey::create().expect("Could not create workgroup key");
+            WorkgroupKey::create(std::env::var_os("HOME").as_deref())
+                .expect("Could not create workgroup key");
         }
-        Command::Env(_) => println!("{}", include_str!("shell.sh").replace("<exec>", &exec)),
+        Command::Env(_) => println!("{}", env_script(&exec)),
         Command::Chain(_) => {
-            let Ok(key) = WorkgroupKey::load() else {
+            let Ok(key) = WorkgroupKey::load(std::env::var_os("HOME").as_deref()) else {
                 return;
             };
-            let mut ssh_chain = SshChain::open(Some(&key)).0;
+            let mut ssh_chain = SshChain::open(
+                Some(&key),
+                std::env::var("WORKGROUP_CHAIN").ok().as_deref(),
+                std::env::var("SSH_CONNECTION").ok().as_deref(),
+            )
+            .0;
             ssh_chain.push(
                 rustix::system::uname()
                     .nodename()
@@ -269,9 +511,313 @@ fn main() {
             println!("{}", SshChain(ssh_chain).seal(&key));
         }
         Command::Run(run) => run_statusline(run),
+        Command::Bench(bench) => run_bench(&bench),
+        Command::DetectVirt(DetectVirt { vm, container }) => {
+            let root = Sysroot::host();
+            let detected = match (vm, container) {
+                (true, false) => virt::detect_vm(&root),
+                (false, true) => virt::detect_container(&root),
+                _ => virt::detect(&root),
+            };
+            match detected.map(|virt| virt.and_then(Virtualization::reported)) {
+                Ok(Some(virt)) => println!("{}", virt.as_str()),
+                Ok(None) => {
+                    println!("none");
+                    std::process::exit(1);
+                }
+                Err(err) => {
+                    eprintln!("statusline detect-virt: {err:#}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::Doctor(Doctor { json }) => {
+            let checks = doctor::diagnose(&exec);
+            if json {
+                println!("{}", doctor::json(&checks));
+            } else {
+                print!("{}", doctor::human(&checks));
+            }
+        }
+        Command::Background(query) => run_background(&query),
+        Command::NerdWidth(query) => run_nerd_width(&query),
+        Command::Title(title) => run_title(&title),
+        Command::Transient(transient) => run_transient(&transient),
+        Command::Render(render) => run_render(render),
+        Command::Tmux(tmux) => run_tmux(tmux),
+        Command::Bar(bar) => {
+            let names = block_names("bar", &bar.blocks);
+            let mode = icon_mode(
+                bar.mode
+                    .or_else(|| std::env::var("PS1_MODE").ok())
+                    .as_deref(),
+            );
+            bar::serve(&names, mode, bar.interval.map(Duration::from_secs));
+        }
+        Command::Daemon(_) => {
+            if let Err(err) = daemon::serve() {
+                eprintln!("statusline daemon: {err:#}");
+                std::process::exit(1);
+            }
+        }
     }
 }
 
+fn run_transient(transient: &Transient) {
+    let mode = icon_mode(transient.mode.as_deref());
+    let environ = Environment::from(Run {
+        return_code: transient.return_code,
+        jobs_count: transient.jobs_count,
+        elapsed_time: transient.elapsed_time,
+        ..Run::default()
+    });
+    let terminal_width: usize = terminal_size::terminal_size()
+        .map_or(80, |(w, _h)| w.0)
+        .into();
+    let collapsed =
+        Statusline::new(&environ).collapse(mode, &environ, terminal_width, &transient.command);
+    print!("{}", render::Ansi.finish(&collapsed));
+}
+
+fn run_title(title: &Title) {
+    let environ = Environment::from(Run::default());
+    if let Some(made) = title::make(&environ, &title.command) {
+        print!("{}", render::Ansi.finish(&style::title(&made)));
+    }
+}
+
+/// Sets theme and color depth from environment
+fn load_colors() {
+    // Broken settings fall back to the defaults, `doctor` tells what is wrong with them
+    if let Ok(palette) = Palette::load(|name| std::env::var(name).ok()) {
+        palette::set(palette);
+    }
+    let background = Background::from_name(std::env::var("PS1_BACKGROUND").ok().as_deref());
+    if let Ok(theme) = Theme::load(std::env::var("PS1_THEME").ok().as_deref(), background) {
+        theme::set(theme);
+    }
+    style::set_depth(capability::color_depth(
+        |name| std::env::var(name).ok(),
+        &Sysroot::host(),
+    ));
+    powerline::set(Separator::from_name(
+        std::env::var("PS1_SEGMENTS").ok().as_deref(),
+    ));
+    width::set_ambiguous_wide(width::ambiguous_wide(|name| std::env::var(name).ok()));
+    width::set_nerd_wide(width::nerd_wide(|name| std::env::var(name).ok()));
+}
+
+/// Bash commands for `env`, stamped so that `doctor` can tell whether they are outdated
+fn env_script(exec: &str) -> String {
+    let script = include_str!("shell.sh").replace("<exec>", exec);
+    // FNV-1a, just to tell scripts apart
+    let stamp = script
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    script.replace("<stamp>", &format!("{stamp:016x}"))
+}
+
+const BOTTOM: &[&str] = &["root_shell"];
+const RIGHT: &[&str] = &["elapsed", "return_code", "time"];
+const MIDDLE: &[&str] = &["workdir"];
+const LEFT: &[&str] = &[
+    "host_user",
+    "container",
+    "ssh",
+    "git_repo",
+    "git_tree",
+    "build_info",
+    "nix_shell",
+    "venv",
+    "jobs",
+    "unseen_mail",
+];
+
+/// Blocks which are left of the prompt in transient mode
+const TRANSIENT: &[&str] = &["time", "return_code", "workdir"];
+const LINE_BLOCKS: &str = "host_user,git_repo,git_tree,venv";
+const BAR_BLOCKS: &str = "host_user,unseen_mail,time";
+
+fn icon_mode(mode: Option<&str>) -> IconMode {
+    let Some(name) = mode.filter(|name| !name.is_empty()) else {
+        return IconMode::Icons;
+    };
+    if let Some(builtin) = IconMode::builtin(name) {
+        return builtin;
+    }
+    // Broken icon sets fall back to the default icons, `doctor` tells what is wrong with them
+    match IconSet::load(name, |var| std::env::var(var).ok()) {
+        Ok(icons) => {
+            let base = icons.base();
+            icon::set(icons);
+            base
+        }
+        Err(_) => IconMode::Icons,
+    }
+}
+
+fn run_bench(bench: &Bench) {
+    let mode = icon_mode(bench.mode.as_deref());
+    profile::enable();
+
+    for _ in 0..bench.runs {
+        let environ = profile::span("build", "environment", || Environment::from(Run::default()));
+        for (line, names) in [
+            ("bottom", BOTTOM),
+            ("right", RIGHT),
+            ("middle", MIDDLE),
+            ("left", LEFT),
+        ] {
+            let mut present = vec![];
+            let mut blocks = vec![];
+            for name in names {
+                let created = profile::span("new", name, || create_blocks(&[name], &environ));
+                present.extend(created.iter().map(|_| name));
+                blocks.extend(created);
+            }
+            profile::span("render", line, || pretty(&blocks, mode));
+            for (name, block) in std::iter::zip(present, &mut blocks) {
+                profile::span("extend", name, || block.extend());
+            }
+            profile::span("render", &format!("{line} extended"), || {
+                pretty(&blocks, mode)
+            });
+        }
+    }
+
+    let timings = profile::take();
+    if bench.json {
+        println!("{}", profile::json(&timings));
+    } else {
+        print!("{}", profile::table(&timings));
+    }
+}
+
+/// Parses comma-separated block names, exiting if some block does not exist
+fn block_names<'a>(command: &str, blocks: &'a str) -> Vec<&'a str> {
+    let names: Vec<&str> = blocks.split(',').map(str::trim).collect();
+    if let Some(unknown) = names.iter().find(|name| !block::is_block(name)) {
+        eprintln!("statusline {command}: unknown block {unknown:?}");
+        std::process::exit(1);
+    }
+    names
+}
+
+fn run_colorize(colorize: Colorize) {
+    let mut names: Vec<String> = colorize.what.into_iter().collect();
+    if colorize.list {
+        names.extend(
+            std::io::stdin()
+                .lines()
+                .map_while(Result::ok)
+                .map(|line| line.trim().to_owned())
+                .filter(|line| !line.is_empty()),
+        );
+    }
+    for name in &names {
+        let mut res = String::new();
+        res.with_style(theme::hashed(name), Style::BOLD, |f| write!(f, "{name}"))
+            .unwrap();
+        println!("{res}");
+    }
+    if !colorize.list {
+        return;
+    }
+
+    let names: Vec<&str> = names.iter().map(String::as_str).collect();
+    for (one, other, same) in palette::collisions(&names, theme::hashed) {
+        let how = if same {
+            "have the same color"
+        } else {
+            "look alike"
+        };
+        println!("{one} and {other} {how}");
+    }
+}
+
+fn run_background(query: &BackgroundQuery) {
+    // Terminals which do not know OSC 11 stay silent
+    let background = tty::query_background(Duration::from_millis(query.timeout));
+    println!(
+        "{}",
+        background.map_or("unknown", |color| Background::of(color).as_str())
+    );
+}
+
+fn run_nerd_width(query: &NerdWidthQuery) {
+    let width = tty::probe_width(width::PROBE, Duration::from_millis(query.timeout));
+    match width {
+        Some(width @ (1 | 2)) => println!("{width}"),
+        _ => println!("unknown"),
+    }
+}
+
+fn run_render(render: Render) {
+    let Some(backend) = render::by_name(&render.backend) else {
+        let known: Vec<_> = render::names().collect();
+        eprintln!(
+            "statusline render: unknown backend {:?}, expected one of {}",
+            render.backend,
+            known.join(", ")
+        );
+        std::process::exit(1);
+    };
+    let names = block_names("render", &render.blocks);
+    let mode = icon_mode(
+        render
+            .mode
+            .or_else(|| std::env::var("PS1_MODE").ok())
+            .as_deref(),
+    );
+
+    let environ = match render.path {
+        Some(path) => {
+            let mut environ = Environment::at(path.clone(), path.clone());
+            environ.vars.insert("PWD".into(), path.into());
+            environ
+        }
+        None => Environment::at(
+            std::env::current_dir().unwrap_or_else(|_| PathBuf::from("/")),
+            PathBuf::from("."),
+        ),
+    };
+
+    println!("{}", render_line(&names, &environ, mode, backend));
+}
+
+fn run_tmux(tmux: Tmux) {
+    let names = block_names("tmux", &tmux.blocks);
+    let mode = icon_mode(
+        tmux.mode
+            .or_else(|| std::env::var("PS1_MODE").ok())
+            .as_deref(),
+    );
+
+    let mut environ = Environment::at(tmux.pane_path.clone(), tmux.pane_path.clone());
+    environ.vars.insert("PWD".into(), tmux.pane_path.into());
+
+    println!("{}", render_line(&names, &environ, mode, &render::Tmux));
+}
+
+/// Blocks in one line, written with given backend
+fn render_line(
+    names: &[&str],
+    environ: &Environment,
+    mode: IconMode,
+    backend: &'static dyn render::RenderBackend,
+) -> String {
+    render::set(backend);
+    let mut blocks = create_blocks(names, environ);
+    for block in &mut blocks {
+        block.extend();
+    }
+    let line = backend.finish(&pretty(&blocks, mode));
+    render::set(&render::Readline);
+    line
+}
+
 fn run_statusline(run: Run) {
     if let Some(fd) = run.control_fd {
         // SAFETY: This file descriptor is already open
@@ -281,120 +827,247 @@ fn run_statusline(run: Run) {
         rustix::fs::fcntl_setfl(fd, OFlags::ASYNC).unwrap();
     }
 
-    let mode = match run.mode.as_deref() {
-        Some("text") => IconMode::Text,
-        Some("minimal") => IconMode::MinimalIcons,
-        _ => IconMode::Icons,
-    };
-
+    let mode = icon_mode(run.mode.as_deref());
+    let format = run.format.clone();
+    let redraw = run.redraw;
     let environ: Environment = run.into();
 
-    let bottom = create_blocks(&["root_shell"], &environ);
-
-    let right = create_blocks(&["elapsed", "return_code", "time"], &environ);
-
-    let middle = create_blocks(&["workdir"], &environ);
-
-    let mut left = create_blocks(
-        &[
-            "host_user",
-            "ssh",
-            "git_repo",
-            "git_tree",
-            "build_info",
-            "nix_shell",
-            "venv",
-            "jobs",
-            "unseen_mail",
-        ],
-        &environ,
-    );
-
-    print_statusline(mode, &environ, &mut left, &middle, &right, &bottom);
-}
-
-fn print_statusline(
-    mode: IconMode,
-    environ: &Environment,
-    left: &mut [Box<dyn Block>],
-    middle: &[Box<dyn Block>],
-    right: &[Box<dyn Block>],
-    bottom: &[Box<dyn Block>],
-) {
-    let middle = pretty(middle, mode);
-    let right = pretty(right, mode);
-    let bottom = pretty(bottom, mode);
-
-    let cont = if let IconMode::Text = mode {
-        ">"
-    } else {
-        "\u{f105}"
-    };
+    match format.as_deref() {
+        None => {}
+        Some("json") => {
+            println!("{}", blocks_json(&environ));
+            return;
+        }
+        Some(other) => {
+            eprintln!("statusline run: unknown format {other:?}");
+            std::process::exit(1);
+        }
+    }
 
     let terminal_width: usize = terminal_size::terminal_size()
         .map_or(80, |(w, _h)| w.0)
         .into();
 
-    let right_length = readline_width(&right);
-    let right_formatted = format!(
-        "{}{right}",
-        // XXX: This may not be the right way to set right prompt...
-        horizontal_absolute(terminal_width.saturating_sub(right_length))
-    );
+    let mut statusline = Statusline::new(&environ);
+    match redraw {
+        Some(previous_width) => {
+            statusline.redraw(mode, previous_width, terminal_width, &mut Terminal);
+        }
+        None => statusline.print(mode, &environ, terminal_width, &mut Terminal),
+    }
+}
+
+/// Data of all blocks by their names, with blocks having nothing to show being `null`
+fn blocks_json(environ: &Environment) -> Value {
+    let mut blocks = vec![];
+    for name in LEFT.iter().chain(MIDDLE).chain(RIGHT).chain(BOTTOM) {
+        let data = create_blocks(&[name], environ).pop().map(|mut block| {
+            block.extend();
+            block.json()
+        });
+        blocks.push((*name, data.into()));
+    }
+    Value::Object(blocks)
+}
+
+/// Where the statusline is printed to
+trait Output {
+    /// Draws directly on the terminal, unseen by readline
+    fn draw(&mut self, s: &str);
+    /// Hands the prompt over to bash, which does not wait for anything drawn after it
+    fn prompt(&mut self, s: &str);
+}
 
-    let left_formatted = pretty(left, mode);
+struct Terminal;
 
-    let three_line_mode =
-        readline_width(&left_formatted) + readline_width(&middle) + right_length + 16
-            >= terminal_width;
+impl Output for Terminal {
+    fn draw(&mut self, s: &str) {
+        eprint!("{s}");
+    }
+
+    fn prompt(&mut self, s: &str) {
+        print!("{s}");
+        std::io::stdout().flush().unwrap();
+        rustix::stdio::dup2_stdout(
+            rustix::fs::open("/dev/null", OFlags::RDWR, Mode::empty()).unwrap(),
+        )
+        .unwrap();
+    }
+}
 
-    let prologue = crate::style::prologue(three_line_mode);
-    let epilogue = crate::style::epilogue();
+struct Statusline {
+    left: Vec<Box<dyn Block>>,
+    middle: Vec<Box<dyn Block>>,
+    right: Vec<Box<dyn Block>>,
+    bottom: Vec<Box<dyn Block>>,
+}
+
+impl Statusline {
+    fn new(environ: &Environment) -> Self {
+        Statusline {
+            bottom: create_blocks(BOTTOM, environ),
+            right: create_blocks(RIGHT, environ),
+            middle: create_blocks(MIDDLE, environ),
+            left: create_blocks(LEFT, environ),
+        }
+    }
+
+    fn print(
+        &mut self,
+        mode: IconMode,
+        environ: &Environment,
+        terminal_width: usize,
+        output: &mut impl Output,
+    ) {
+        let three_line_mode = self.three_line_mode(mode, terminal_width);
+        self.fit(mode, terminal_width, three_line_mode);
+
+        output.draw(&crate::style::command_finished(environ.ret_code));
+        output.draw(&crate::style::prompt_start());
+        output.draw(&crate::style::current_dir(&crate::link::file(
+            &environ.host,
+            &environ.work_dir,
+        )));
+        if let Some(title) = title::make(environ, "") {
+            output.draw(&crate::style::title(&title));
+        }
 
-    let eprint_top_part = |top| {
         if three_line_mode {
-            eprint!("{prologue}{top}{right_formatted}\n{cont} {middle}{epilogue}");
+            output.draw("\n\n\n");
         } else {
-            eprint!("{prologue}{top} {middle}{right_formatted}{epilogue}");
+            output.draw("\n\n");
         }
-    };
+        output.draw(&self.top_part(mode, three_line_mode));
 
-    let title = make_title(environ);
-    eprint!("{title}");
+        output.prompt(&format!(
+            "{} {}",
+            pretty(&self.bottom, mode),
+            crate::style::prompt_end()
+        ));
 
-    if three_line_mode {
-        eprint!("\n\n\n");
-    } else {
-        eprint!("\n\n");
+        for block in &mut self.left {
+            block.extend();
+        }
+        self.fit(mode, terminal_width, three_line_mode);
+        output.draw(&self.top_part(mode, three_line_mode));
     }
-    eprint_top_part(left_formatted);
 
-    print!("{bottom} ");
-    std::io::stdout().flush().unwrap();
-    rustix::stdio::dup2_stdout(rustix::fs::open("/dev/null", OFlags::RDWR, Mode::empty()).unwrap())
-        .unwrap();
+    /// Draws lines above the prompt again after terminal was resized, keeping as many of them as
+    /// were drawn for `previous_width`
+    fn redraw(
+        &mut self,
+        mode: IconMode,
+        previous_width: usize,
+        terminal_width: usize,
+        output: &mut impl Output,
+    ) {
+        let three_line_mode = self.three_line_mode(mode, previous_width);
+        for block in &mut self.left {
+            block.extend();
+        }
+        self.fit(mode, terminal_width, three_line_mode);
+        output.draw(&self.top_part(mode, three_line_mode));
+    }
 
-    for block in &mut *left {
-        block.extend();
+    /// Whether middle part gets its own line, which is decided before blocks are extended
+    fn three_line_mode(&self, mode: IconMode, terminal_width: usize) -> bool {
+        pretty_width(&self.left, mode)
+            + pretty_width(&self.middle, mode)
+            + pretty_width(&self.right, mode)
+            + 16
+            >= terminal_width
     }
-    eprint_top_part(pretty(left, mode));
-}
 
-fn make_title(env: &Environment) -> String {
-    let pwd = if let Some((home, user)) = &env.current_home {
-        let wd = env.work_dir.strip_prefix(home).unwrap_or(&env.work_dir);
-        if wd.as_os_str().is_empty() {
-            format_args!("~{}", *user)
+    /// Replaces lines drawn above the prompt, which `command` was typed in, with one line of
+    /// compact blocks
+    fn collapse(
+        &self,
+        mode: IconMode,
+        environ: &Environment,
+        terminal_width: usize,
+        command: &str,
+    ) -> String {
+        let top_lines = if self.three_line_mode(mode, terminal_width) {
+            2
         } else {
-            format_args!("~{}/{}", *user, wd.display())
+            1
+        };
+        // Same prompt as `print` gives to readline
+        let prompt_width = pretty_width(&self.bottom, mode) + 1;
+        let mut command_lines = 0;
+        for (idx, line) in command.trim().lines().enumerate() {
+            // Continuation lines start with default `PS2`
+            let width = width::of(line) + if idx == 0 { prompt_width } else { 2 };
+            // Readline wraps a full line before the cursor gets out of it
+            command_lines += width / terminal_width.max(1) + 1;
         }
-    } else {
-        format_args!("{}", env.work_dir.display())
-    };
-    crate::style::title(&format!("{}@{}: {}", env.user, env.host, pwd))
+        let command_lines = command_lines.max(1);
+
+        let mut compact = create_blocks(TRANSIENT, environ);
+        for block in &mut compact {
+            block.compact();
+        }
+        layout::fit(&mut [&mut compact], terminal_width, 0, mode);
+        crate::style::collapse(
+            &pretty(&compact, mode),
+            top_lines + command_lines,
+            top_lines,
+        )
+    }
+
+    /// Compacts and drops blocks until every line above the prompt fits into terminal
+    fn fit(&mut self, mode: IconMode, terminal_width: usize, three_line_mode: bool) {
+        if three_line_mode {
+            // Space between left and right, and continuation mark with space before middle
+            layout::fit(
+                &mut [&mut self.left, &mut self.right],
+                terminal_width,
+                1,
+                mode,
+            );
+            let cont = width::of(crate::icon::continuation(mode)) + 1;
+            layout::fit(&mut [&mut self.middle], terminal_width, cont, mode);
+        } else {
+            layout::fit(
+                &mut [&mut self.left, &mut self.middle, &mut self.right],
+                terminal_width,
+                2,
+                mode,
+            );
+        }
+    }
+
+    fn top_part(&self, mode: IconMode, three_line_mode: bool) -> String {
+        let left = pretty(&self.left, mode);
+        let middle = pretty(&self.middle, mode);
+        let right = pretty(&self.right, mode);
+
+        let cont = crate::icon::continuation(mode);
+
+        let right_formatted = format!("{}{right}", right_aligned(width::of(&right)));
+
+        let prologue = crate::style::prologue(three_line_mode);
+        let epilogue = crate::style::epilogue();
+
+        if three_line_mode {
+            format!(
+                "{prologue}{left}{right_formatted}\n{}{cont} {middle}{epilogue}",
+                crate::style::clear_line()
+            )
+        } else {
+            format!("{prologue}{left} {middle}{right_formatted}{epilogue}")
+        }
+    }
+}
+
+fn pretty_width(line: &[Box<dyn Block>], mode: IconMode) -> usize {
+    width::of(&pretty(line, mode))
 }
 
 fn pretty(line: &[Box<dyn Block>], mode: IconMode) -> String {
+    if powerline::active() {
+        return powerline::join(line, mode);
+    }
     let mut res = String::new();
     for block in line {
         let prev_len = res.len();
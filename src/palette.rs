@@ -0,0 +1,282 @@
+//! Colors of hostnames, usernames and other names, which are derived from the names themselves
+
+use crate::{
+    config,
+    style::{self, Color},
+    theme::Background,
+};
+use anyhow::{Context as _, Result, bail};
+use std::{cell::RefCell, path::PathBuf};
+
+type Rgb = (u8, u8, u8);
+
+/// Saturated hues, red is left out as it is reserved for root
+const HSV: &[Rgb] = &[
+    (255, 85, 0),
+    (255, 128, 0),
+    (255, 170, 0),
+    (255, 213, 0),
+    (255, 255, 0),
+    (213, 255, 0),
+    (170, 255, 0),
+    (128, 255, 0),
+    (0, 255, 85),
+    (0, 255, 128),
+    (0, 255, 170),
+    (0, 255, 213),
+    (0, 213, 255),
+    (0, 128, 255),
+    (0, 85, 255),
+    (128, 0, 255),
+    (170, 0, 255),
+    (213, 0, 255),
+    (255, 0, 255),
+    (255, 0, 212),
+    (255, 0, 170),
+    (255, 0, 128),
+    (255, 0, 85),
+];
+
+/// Okabe and Ito's colors, told apart with any kind of color blindness
+const OKABE_ITO: &[Rgb] = &[
+    (230, 159, 0),
+    (86, 180, 233),
+    (0, 158, 115),
+    (240, 228, 66),
+    (0, 114, 178),
+    (213, 94, 0),
+    (204, 121, 167),
+];
+
+/// Paul Tol's bright scheme, also safe for color blindness
+const TOL: &[Rgb] = &[
+    (68, 119, 170),
+    (102, 204, 238),
+    (34, 136, 51),
+    (204, 187, 68),
+    (238, 102, 119),
+    (170, 51, 119),
+    (187, 187, 187),
+];
+
+/// IBM Design Library's scheme, few colors but very distinct
+const IBM: &[Rgb] = &[
+    (100, 143, 255),
+    (120, 94, 240),
+    (220, 38, 127),
+    (254, 97, 0),
+    (255, 176, 0),
+];
+
+const PALETTES: &[(&str, &[Rgb])] = &[
+    ("hsv", HSV),
+    ("okabe-ito", OKABE_ITO),
+    ("tol", TOL),
+    ("ibm", IBM),
+];
+
+/// Colors to choose from, and names which have their colors set explicitly
+pub struct Palette {
+    colors: &'static [Rgb],
+    mapping: Vec<(String, Color)>,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette {
+            colors: HSV,
+            mapping: vec![],
+        }
+    }
+}
+
+impl Palette {
+    pub fn names() -> impl Iterator<Item = &'static str> {
+        PALETTES.iter().map(|(name, _)| *name)
+    }
+
+    /// `PS1_HASH_PALETTE` names the palette, and mapping file is read from `PS1_COLORS` or
+    /// `~/.config/statusline/colors.toml` if it exists
+    pub fn load(var: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let set = |name: &str| var(name).filter(|value| !value.is_empty());
+        let mut palette = Self::default();
+        if let Some(name) = set("PS1_HASH_PALETTE") {
+            palette.colors = PALETTES
+                .iter()
+                .find_map(|&(known, colors)| (known == name).then_some(colors))
+                .with_context(|| format!("no palette {name:?}"))?;
+        }
+
+        let Some(path) = mapping_path(set) else {
+            return Ok(palette);
+        };
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("can not read {}", path.display()))?;
+        palette.mapping = parse_mapping(&text).with_context(|| format!("in {}", path.display()))?;
+        Ok(palette)
+    }
+
+    /// Color of the name, hashed ones are darker on light background to be readable
+    pub fn color_of(&self, what: &str, background: Background) -> Color {
+        if let Some((_, color)) = self.mapping.iter().find(|(name, _)| name == what) {
+            return *color;
+        }
+        if what == "root" {
+            return Color::RED;
+        }
+        let idx = polyhash(what, self.colors.len(), 179, what.len());
+        let (r, g, b) = self.colors[idx];
+        match background {
+            Background::Dark => Color::True(r, g, b),
+            Background::Light => {
+                let darken = |channel: u8| channel / 5 * 3;
+                Color::True(darken(r), darken(g), darken(b))
+            }
+        }
+    }
+}
+
+/// Mapping file set explicitly, or the default one if it exists
+fn mapping_path(set: impl Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    if let Some(path) = set("PS1_COLORS") {
+        return Some(PathBuf::from(path));
+    }
+    let config = set("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| set("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config.join("statusline/colors.toml")).filter(|path| path.exists())
+}
+
+/// Top-level `name = "color"` pairs
+fn parse_mapping(text: &str) -> Result<Vec<(String, Color)>> {
+    let mut mapping = vec![];
+    for entry in config::parse(text)? {
+        if !entry.table.is_empty() {
+            bail!("line {}: tables are not expected", entry.line);
+        }
+        let color = entry
+            .value
+            .as_str()
+            .and_then(Color::parse)
+            .with_context(|| format!("line {}: bad color", entry.line))?;
+        mapping.push((entry.key, color));
+    }
+    Ok(mapping)
+}
+
+/// Pairs of names which have the same color, or colors too close to tell apart
+pub fn collisions<'a>(
+    names: &[&'a str],
+    color: impl Fn(&str) -> Color,
+) -> Vec<(&'a str, &'a str, bool)> {
+    // Closer than 60 in RGB space
+    const ALIKE: u32 = 60 * 60;
+    let colors: Vec<_> = names.iter().map(|name| color(name).rgb()).collect();
+    let mut res = vec![];
+    for (idx, (one, one_color)) in names.iter().zip(&colors).enumerate() {
+        for (other, other_color) in names.iter().zip(&colors).skip(idx + 1) {
+            let distance = style::distance(*one_color, *other_color);
+            if distance < ALIKE {
+                res.push((*one, *other, distance == 0));
+            }
+        }
+    }
+    res
+}
+
+fn polyhash(s: &str, m: usize, p: usize, h_init: usize) -> usize {
+    let mut h = h_init % m;
+    for by in s.bytes() {
+        h = (h * p + by as usize) % m;
+    }
+    h
+}
+
+thread_local! {
+    static PALETTE: RefCell<Palette> = RefCell::new(Palette::default());
+}
+
+/// Sets palette used to render on this thread
+pub fn set(palette: Palette) {
+    PALETTE.set(palette);
+}
+
+pub fn color_of(what: &str, background: Background) -> Color {
+    PALETTE.with_borrow(|palette| palette.color_of(what, background))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Palette, collisions, parse_mapping};
+    use crate::{style::Color, theme::Background};
+
+    #[test]
+    fn finds_collisions() {
+        let color = |name: &str| match name {
+            "alice" | "bob" => Color::True(10, 10, 10),
+            "carol" => Color::True(40, 40, 40),
+            _ => Color::True(200, 0, 0),
+        };
+        assert_eq!(
+            collisions(&["alice", "bob", "carol", "dave"], color),
+            [
+                ("alice", "bob", true),
+                ("alice", "carol", false),
+                ("bob", "carol", false),
+            ],
+        );
+        assert_eq!(collisions(&["alice", "dave"], color), []);
+    }
+
+    #[test]
+    fn colors_names() {
+        let hashed = Palette::default();
+        let mapped = Palette {
+            mapping: parse_mapping("alice = \"#102030\"\nbob = \"bright-blue\"\n").unwrap(),
+            ..Palette::default()
+        };
+        for background in [Background::Dark, Background::Light] {
+            assert_eq!(hashed.color_of("root", background), Color::RED);
+            assert_eq!(mapped.color_of("root", background), Color::RED);
+            assert_eq!(
+                mapped.color_of("alice", background),
+                Color::True(16, 32, 48)
+            );
+            assert_eq!(mapped.color_of("bob", background), Color::High8(4));
+            assert_eq!(
+                mapped.color_of("carol", background),
+                hashed.color_of("carol", background),
+            );
+        }
+        for name in ["alice", "bob", "carol", "localhost"] {
+            let Color::True(r, g, b) = hashed.color_of(name, Background::Dark) else {
+                panic!("{name:?} is not hashed");
+            };
+            let darken = |channel: u8| channel / 5 * 3;
+            assert_eq!(
+                hashed.color_of(name, Background::Light),
+                Color::True(darken(r), darken(g), darken(b)),
+                "{name:?}",
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_bad_mapping() {
+        for (text, expected) in [
+            ("alice = \"nope\"\n", "line 1: bad color"),
+            ("alice = \"#12345\"\n", "line 1: bad color"),
+            ("alice = 1\n", "line 1: bad color"),
+            (
+                "alice = \"red\"\n[users]\nbob = \"blue\"\n",
+                "line 3: tables are not expected",
+            ),
+        ] {
+            let err = parse_mapping(text).unwrap_err();
+            assert_eq!(err.to_string(), expected, "{text:?}");
+        }
+        let var = |name: &str| (name == "PS1_HASH_PALETTE").then(|| "rainbow".to_owned());
+        let err = Palette::load(var).err().unwrap();
+        assert_eq!(err.to_string(), "no palette \"rainbow\"");
+    }
+}
@@ -0,0 +1,197 @@
+//! Segment style, where blocks are drawn on backgrounds of their colors and joined by powerline
+//! separators instead of being put in brackets
+
+use crate::{
+    Block, IconMode, render,
+    style::{self, Color, ColorDepth, INVISIBLE_END, INVISIBLE_START, Style},
+    theme::Background,
+};
+use std::{cell::Cell, fmt::Write as _};
+
+/// How blocks are told apart
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Separator {
+    /// `[ … ]` with colored text
+    Brackets,
+    Plain,
+    Rounded,
+    Slanted,
+}
+
+impl Separator {
+    /// Separator named by `PS1_SEGMENTS`, brackets if it is not set or unknown
+    pub fn from_name(name: Option<&str>) -> Self {
+        match name {
+            Some("plain") => Self::Plain,
+            Some("rounded") => Self::Rounded,
+            Some("slanted") => Self::Slanted,
+            _ => Self::Brackets,
+        }
+    }
+
+    /// Glyph drawn between segments, text mode relies on padding and backgrounds alone
+    fn glyph(self, mode: IconMode) -> &'static str {
+        match (self, mode) {
+            (Self::Brackets, _) | (_, IconMode::Text) => "",
+            (Self::Plain, _) => "\u{e0b0}",
+            (Self::Rounded, _) => "\u{e0b4}",
+            (Self::Slanted, _) => "\u{e0bc}",
+        }
+    }
+
+    /// Outline of the glyph, drawn between segments of the same background
+    fn thin_glyph(self, mode: IconMode) -> &'static str {
+        match (self, mode) {
+            (Self::Brackets, _) | (_, IconMode::Text) => "",
+            (Self::Plain, _) => "\u{e0b1}",
+            (Self::Rounded, _) => "\u{e0b5}",
+            (Self::Slanted, _) => "\u{e0bd}",
+        }
+    }
+}
+
+/// Background of blocks which do not draw segments of their own
+const NEUTRAL: Color = Color::True(58, 58, 58);
+
+#[derive(Clone, Copy)]
+struct Segment {
+    background: Color,
+    /// Neutral segments keep colors of the text drawn on them, others show it in one color
+    keeps_colors: bool,
+}
+
+impl Segment {
+    /// Text color readable on the background
+    fn foreground(self) -> Color {
+        match Background::of(self.background.rgb()) {
+            Background::Light => Color::Low8(0),
+            Background::Dark => Color::High8(7),
+        }
+    }
+}
+
+thread_local! {
+    static SEPARATOR: Cell<Separator> = const { Cell::new(Separator::Brackets) };
+    /// Icon mode of the line being joined, nothing outside of [`join`]
+    static MODE: Cell<Option<IconMode>> = const { Cell::new(None) };
+    /// Segment being drawn
+    static CURRENT: Cell<Option<Segment>> = const { Cell::new(None) };
+    /// Segment before the neutral one started speculatively for the block being joined
+    static PREVIOUS: Cell<Option<Segment>> = const { Cell::new(None) };
+    /// Whether the block being joined has started segments of its own
+    static STARTED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Sets separator used on this thread
+pub fn set(separator: Separator) {
+    SEPARATOR.set(separator);
+}
+
+/// Segments need backgrounds, so they are drawn only if both the backend and the terminal have
+/// them
+pub fn active() -> bool {
+    SEPARATOR.get() != Separator::Brackets
+        && style::depth() != ColorDepth::None
+        && render::current()
+            .segment(Color::GRAY, Color::GRAY)
+            .is_some()
+}
+
+fn markup(fg: Color, bg: Color) -> String {
+    let markup = render::current().segment(fg, bg).unwrap_or_default();
+    format!("{INVISIBLE_START}{markup}{INVISIBLE_END}")
+}
+
+/// Ends the current segment and starts `next`, with left padding
+fn transition(next: Segment) -> String {
+    let mut res = String::new();
+    if let (Some(prev), Some(mode)) = (CURRENT.replace(Some(next)), MODE.get()) {
+        if prev.background == next.background {
+            res.push_str(&markup(next.foreground(), next.background));
+            res.push_str(SEPARATOR.get().thin_glyph(mode));
+        } else {
+            res.push_str(&markup(prev.background, next.background));
+            res.push_str(SEPARATOR.get().glyph(mode));
+        }
+    }
+    res.push_str(&markup(next.foreground(), next.background));
+    res.push(' ');
+    res
+}
+
+/// Whether a line is being joined in segment style, where blocks are not separated by spaces
+pub fn joining() -> bool {
+    MODE.get().is_some()
+}
+
+/// Starts segment on `background` when joining a line in segment style, `None` means that
+/// brackets should be drawn instead
+pub fn start(background: Color) -> Option<String> {
+    MODE.get()?;
+    // Neutral segment of the block is dropped, the first segment follows the previous block
+    if !STARTED.replace(true) {
+        CURRENT.set(PREVIOUS.get());
+    }
+    Some(transition(Segment {
+        background,
+        keeps_colors: false,
+    }))
+}
+
+/// Markup for styled text inside of the current segment
+pub fn styled(color: Color, style: Style) -> Option<String> {
+    let segment = CURRENT.get()?;
+    let fg = if segment.keeps_colors {
+        color
+    } else {
+        segment.foreground()
+    };
+    Some(format!(
+        "{}{style}",
+        render::current().segment(fg, segment.background)?
+    ))
+}
+
+/// Markup which returns to the current segment after styled text
+pub fn restore() -> Option<String> {
+    let segment = CURRENT.get()?;
+    render::current().segment(segment.foreground(), segment.background)
+}
+
+/// Draws `line` as segments, blocks without segments of their own get neutral ones
+pub fn join(line: &[Box<dyn Block>], mode: IconMode) -> String {
+    MODE.set(Some(mode));
+    let backend = render::current();
+    let mut res = String::new();
+    for block in line {
+        // Neutral segment is started first, so that styled text of the block returns to it
+        let prev = CURRENT.get();
+        PREVIOUS.set(prev);
+        STARTED.set(false);
+        let start = transition(Segment {
+            background: NEUTRAL,
+            keeps_colors: true,
+        });
+        let body = crate::icon::display(block.as_ref(), mode).to_string();
+        if STARTED.get() {
+            res.push_str(&body);
+        } else if body.is_empty() {
+            CURRENT.set(prev);
+        } else {
+            write!(res, "{start}{body} ").unwrap();
+        }
+    }
+    if let Some(last) = CURRENT.take() {
+        write!(
+            res,
+            "{INVISIBLE_START}{}{}{INVISIBLE_END}{}{INVISIBLE_START}{}{INVISIBLE_END}",
+            backend.end(),
+            backend.start(last.background, Style::empty()),
+            SEPARATOR.get().glyph(mode),
+            backend.end(),
+        )
+        .unwrap();
+    }
+    MODE.set(None);
+    res
+}
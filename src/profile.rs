@@ -0,0 +1,162 @@
+use crate::json::Value;
+use std::{
+    fmt::Write as _,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static SAMPLES: Mutex<Vec<(String, Duration)>> = Mutex::new(vec![]);
+
+/// Starts recording timings of all spans
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Runs `func`, recording how long it took as `<what> <name>` if profiling is enabled
+pub fn span<R>(what: &str, name: &str, func: impl FnOnce() -> R) -> R {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return func();
+    }
+    let start = Instant::now();
+    let res = func();
+    let elapsed = start.elapsed();
+    SAMPLES
+        .lock()
+        .unwrap()
+        .push((format!("{what} {name}"), elapsed));
+    res
+}
+
+/// Timings of one span across all runs
+pub struct Timing {
+    name: String,
+    samples: Vec<Duration>,
+}
+
+impl Timing {
+    /// Zero if there are no samples
+    fn percentile(&self, p: usize) -> Duration {
+        // Nearest-rank method, samples are sorted
+        let rank = (self.samples.len() * p).div_ceil(100).max(1);
+        self.samples.get(rank - 1).copied().unwrap_or_default()
+    }
+}
+
+/// Takes recorded samples, grouped by span in order of first appearance
+pub fn take() -> Vec<Timing> {
+    let mut timings: Vec<Timing> = vec![];
+    for (name, elapsed) in std::mem::take(&mut *SAMPLES.lock().unwrap()) {
+        match timings.iter_mut().find(|timing| timing.name == name) {
+            Some(timing) => timing.samples.push(elapsed),
+            None => timings.push(Timing {
+                name,
+                samples: vec![elapsed],
+            }),
+        }
+    }
+    for timing in &mut timings {
+        timing.samples.sort_unstable();
+    }
+    timings
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+pub fn table(timings: &[Timing]) -> String {
+    let width = timings
+        .iter()
+        .map(|timing| timing.name.len())
+        .max()
+        .unwrap_or_default();
+    let mut res = format!(
+        "{:width$} {:>7} {:>10} {:>10} {:>10}\n",
+        "span", "samples", "p50, ms", "p90, ms", "max, ms"
+    );
+    for timing in timings {
+        writeln!(
+            res,
+            "{:width$} {:>7} {:>10.3} {:>10.3} {:>10.3}",
+            timing.name,
+            timing.samples.len(),
+            millis(timing.percentile(50)),
+            millis(timing.percentile(90)),
+            millis(timing.percentile(100)),
+        )
+        .unwrap();
+    }
+    res
+}
+
+pub fn json(timings: &[Timing]) -> Value {
+    Value::Array(
+        timings
+            .iter()
+            .map(|timing| {
+                Value::Object(vec![
+                    ("span", timing.name.as_str().into()),
+                    ("samples", timing.samples.len().into()),
+                    ("p50_ms", millis(timing.percentile(50)).into()),
+                    ("p90_ms", millis(timing.percentile(90)).into()),
+                    ("max_ms", millis(timing.percentile(100)).into()),
+                ])
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Timing, json, table};
+    use std::time::Duration;
+
+    fn timing(name: &str, millis: impl IntoIterator<Item = u64>) -> Timing {
+        Timing {
+            name: name.to_owned(),
+            samples: millis.into_iter().map(Duration::from_millis).collect(),
+        }
+    }
+
+    #[test]
+    fn takes_percentiles() {
+        for (millis, p50, p95, max) in [
+            ((1..=20).collect(), 10, 19, 20),
+            ((1..=100).collect(), 50, 95, 100),
+            (vec![1, 2, 3], 2, 3, 3),
+            (vec![7], 7, 7, 7),
+            (vec![], 0, 0, 0),
+        ] {
+            let timing = timing("git status", millis.clone());
+            assert_eq!(
+                [50, 95, 100].map(|p| timing.percentile(p)),
+                [p50, p95, max].map(Duration::from_millis),
+                "{millis:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn reports_timings() {
+        let timings = [timing("git status", 1..=10), timing("render", [2])];
+        assert_eq!(
+            table(&timings),
+            "span       samples    p50, ms    p90, ms    max, ms\n\
+             git status      10      5.000      9.000     10.000\n\
+             render           1      2.000      2.000      2.000\n"
+        );
+        assert_eq!(
+            json(&timings).to_string(),
+            r#"[{"span":"git status","samples":10,"p50_ms":5,"p90_ms":9,"max_ms":10},{"span":"render","samples":1,"p50_ms":2,"p90_ms":2,"max_ms":2}]"#
+        );
+        assert_eq!(
+            table(&[]),
+            "span samples    p50, ms    p90, ms    max, ms\n"
+        );
+        assert_eq!(json(&[]).to_string(), "[]");
+    }
+}
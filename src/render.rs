@@ -0,0 +1,328 @@
+use crate::style::{self, CSI, Color, INVISIBLE_END, INVISIBLE_START, RESET, Style};
+use std::cell::Cell;
+
+/// Language in which colors and styles are written
+///
+/// While rendering, markup made by [`RenderBackend::start`] and [`RenderBackend::end`] is put
+/// between `\x01` and `\x02`, and the whole line is turned into the final output by
+/// [`RenderBackend::finish`]. Blocks never see which backend is used.
+pub trait RenderBackend {
+    /// Markup which starts text with given color and style
+    fn start(&self, color: Color, style: Style) -> String;
+    /// Markup which ends styled text
+    fn end(&self) -> String;
+    /// Turns rendered line into the final output
+    fn finish(&self, s: &str) -> String;
+    /// Markup which starts hyperlink to `url`, if the backend has hyperlinks
+    fn link_start(&self, _url: &str) -> String {
+        String::new()
+    }
+    /// Markup which ends hyperlink
+    fn link_end(&self) -> String {
+        String::new()
+    }
+    /// Markup which resets style and starts text with `fg` color on `bg` background, if the
+    /// backend has backgrounds
+    fn segment(&self, _fg: Color, _bg: Color) -> Option<String> {
+        None
+    }
+}
+
+/// ANSI escapes marked invisible for readline, for bash prompt
+pub struct Readline;
+
+/// ANSI escapes in `%{ %}`, for zsh prompt
+pub struct Zsh;
+
+/// ANSI escapes, for terminal
+pub struct Ansi;
+
+/// No colors at all
+pub struct Plain;
+
+/// Pango markup, for GTK-based bars and notifications
+pub struct Pango;
+
+/// `#[fg=...]` style directives, for tmux status line
+pub struct Tmux;
+
+/// Colors as `#rrggbb` kept between invisible markers, to be split by [`segments`]
+pub struct Segments;
+
+const BACKENDS: &[(&str, &dyn RenderBackend)] = &[
+    ("readline", &Readline),
+    ("zsh", &Zsh),
+    ("ansi", &Ansi),
+    ("plain", &Plain),
+    ("pango", &Pango),
+    ("tmux", &Tmux),
+];
+
+thread_local! {
+    static BACKEND: Cell<&'static dyn RenderBackend> = const { Cell::new(&Readline) };
+}
+
+pub fn by_name(name: &str) -> Option<&'static dyn RenderBackend> {
+    BACKENDS
+        .iter()
+        .find_map(|&(backend_name, backend)| (backend_name == name).then_some(backend))
+}
+
+pub fn names() -> impl Iterator<Item = &'static str> {
+    BACKENDS.iter().map(|(name, _)| *name)
+}
+
+/// Sets backend used to render on this thread
+pub fn set(backend: &'static dyn RenderBackend) {
+    BACKEND.set(backend);
+}
+
+pub fn current() -> &'static dyn RenderBackend {
+    BACKEND.get()
+}
+
+/// Rewrites markup between invisible markers and text outside them separately
+fn rewrite(
+    s: &str,
+    mut markup: impl FnMut(&mut String, &str),
+    text: impl Fn(&mut String, char),
+) -> String {
+    let mut res = String::new();
+    let mut parts = s.split(INVISIBLE_START);
+    for c in parts.next().unwrap_or_default().chars() {
+        text(&mut res, c);
+    }
+    for part in parts {
+        let (invisible, rest) = part.split_once(INVISIBLE_END).unwrap_or((part, ""));
+        markup(&mut res, invisible);
+        for c in rest.chars() {
+            text(&mut res, c);
+        }
+    }
+    res
+}
+
+fn ansi(color: Color, style: Style) -> String {
+    format!("{color}{style}")
+}
+
+fn ansi_segment(fg: Color, bg: Color) -> String {
+    format!("{RESET}{fg}{}", bg.background())
+}
+
+impl RenderBackend for Readline {
+    fn start(&self, color: Color, style: Style) -> String {
+        ansi(color, style)
+    }
+
+    fn end(&self) -> String {
+        RESET.to_owned()
+    }
+
+    fn finish(&self, s: &str) -> String {
+        s.to_owned()
+    }
+
+    fn link_start(&self, url: &str) -> String {
+        style::hyperlink(url)
+    }
+
+    fn link_end(&self) -> String {
+        style::hyperlink("")
+    }
+
+    fn segment(&self, fg: Color, bg: Color) -> Option<String> {
+        Some(ansi_segment(fg, bg))
+    }
+}
+
+impl RenderBackend for Zsh {
+    fn start(&self, color: Color, style: Style) -> String {
+        ansi(color, style)
+    }
+
+    fn end(&self) -> String {
+        RESET.to_owned()
+    }
+
+    fn finish(&self, s: &str) -> String {
+        rewrite(
+            s,
+            |res, markup| {
+                res.push_str("%{");
+                res.push_str(markup);
+                res.push_str("%}");
+            },
+            |res, c| match c {
+                '%' => res.push_str("%%"),
+                _ => res.push(c),
+            },
+        )
+    }
+
+    fn link_start(&self, url: &str) -> String {
+        // Percent-encoded URL must not be taken for prompt escapes
+        style::hyperlink(url).replace('%', "%%")
+    }
+
+    fn link_end(&self) -> String {
+        style::hyperlink("")
+    }
+
+    fn segment(&self, fg: Color, bg: Color) -> Option<String> {
+        Some(ansi_segment(fg, bg))
+    }
+}
+
+impl RenderBackend for Ansi {
+    fn start(&self, color: Color, style: Style) -> String {
+        ansi(color, style)
+    }
+
+    fn end(&self) -> String {
+        RESET.to_owned()
+    }
+
+    fn finish(&self, s: &str) -> String {
+        rewrite(s, String::push_str, String::push)
+    }
+
+    fn link_start(&self, url: &str) -> String {
+        style::hyperlink(url)
+    }
+
+    fn link_end(&self) -> String {
+        style::hyperlink("")
+    }
+
+    fn segment(&self, fg: Color, bg: Color) -> Option<String> {
+        Some(ansi_segment(fg, bg))
+    }
+}
+
+impl RenderBackend for Plain {
+    fn start(&self, _: Color, _: Style) -> String {
+        String::new()
+    }
+
+    fn end(&self) -> String {
+        String::new()
+    }
+
+    fn finish(&self, s: &str) -> String {
+        // Drops cursor movement and other escapes too
+        rewrite(s, |_, _| {}, String::push)
+    }
+}
+
+impl RenderBackend for Pango {
+    fn start(&self, color: Color, style: Style) -> String {
+        let mut span = format!("<span foreground=\"{}\"", color.hex());
+        if style.contains(Style::BOLD) {
+            span.push_str(" font_weight=\"bold\"");
+        }
+        if style.contains(Style::ITALIC) {
+            span.push_str(" font_style=\"italic\"");
+        }
+        span.push('>');
+        span
+    }
+
+    fn end(&self) -> String {
+        "</span>".to_owned()
+    }
+
+    fn finish(&self, s: &str) -> String {
+        rewrite(
+            s,
+            |res, markup| {
+                // Terminal escapes outside of spans mean nothing here
+                if !markup.starts_with(CSI) {
+                    res.push_str(markup);
+                }
+            },
+            |res, c| match c {
+                '&' => res.push_str("&amp;"),
+                '<' => res.push_str("&lt;"),
+                '>' => res.push_str("&gt;"),
+                '"' => res.push_str("&quot;"),
+                _ => res.push(c),
+            },
+        )
+    }
+}
+
+fn tmux_color(color: Color) -> String {
+    match color {
+        Color::Low8(low) => format!("colour{low}"),
+        Color::High8(high) => format!("colour{}", 8 + high),
+        Color::True(r, g, b) => format!("#{r:02x}{g:02x}{b:02x}"),
+    }
+}
+
+impl RenderBackend for Tmux {
+    fn start(&self, color: Color, style: Style) -> String {
+        let mut directive = format!("#[fg={}", tmux_color(color));
+        if style.contains(Style::BOLD) {
+            directive.push_str(",bold");
+        }
+        if style.contains(Style::ITALIC) {
+            directive.push_str(",italics");
+        }
+        directive.push(']');
+        directive
+    }
+
+    fn end(&self) -> String {
+        "#[default]".to_owned()
+    }
+
+    fn finish(&self, s: &str) -> String {
+        rewrite(s, String::push_str, |res, c| match c {
+            '#' => res.push_str("##"),
+            _ => res.push(c),
+        })
+    }
+
+    fn segment(&self, fg: Color, bg: Color) -> Option<String> {
+        Some(format!(
+            "#[default,fg={},bg={}]",
+            tmux_color(fg),
+            tmux_color(bg)
+        ))
+    }
+}
+
+impl RenderBackend for Segments {
+    fn start(&self, color: Color, _: Style) -> String {
+        color.hex()
+    }
+
+    fn end(&self) -> String {
+        String::new()
+    }
+
+    fn finish(&self, s: &str) -> String {
+        s.to_owned()
+    }
+}
+
+/// Splits output made with [`Segments`] into pieces of text with their colors
+pub fn segments(s: &str) -> Vec<(String, Option<String>)> {
+    let mut res = vec![];
+    let mut parts = s.split(INVISIBLE_START);
+    let mut text = parts.next().unwrap_or_default().to_owned();
+    let mut color = None;
+    for part in parts {
+        let (markup, rest) = part.split_once(INVISIBLE_END).unwrap_or((part, ""));
+        if !text.is_empty() {
+            res.push((std::mem::take(&mut text), color.take()));
+        }
+        color = (!markup.is_empty()).then(|| markup.to_owned());
+        text.push_str(rest);
+    }
+    if !text.is_empty() {
+        res.push((text, color));
+    }
+    res
+}
@@ -11,10 +11,26 @@ case $TERM in
         ;;
 esac
 
+# Let `statusline doctor` know which script is sourced
+export _SL_STAMP=<stamp>
+
+# Ask terminal whether its background is light once, nested shells inherit the answer
+if [[ -z "$PS1_BACKGROUND" ]]; then
+    export PS1_BACKGROUND="$("<exec>" background)"
+fi
+
+# Same for width of nerd font glyphs, which depends on the font
+if [[ -z "$PS1_NERD_WIDTH" && "$_sl_mode" != text ]]; then
+    export PS1_NERD_WIDTH="$("<exec>" nerd-width)"
+fi
+
 # Disable process lingering if it's not being needed
 exec {_sl_control_fd}<> <(true)
 trap 'echo >&$_sl_control_fd' DEBUG
 
+# Keep COLUMNS up to date, so that lines above the prompt are redrawn for the right width
+shopt -s checkwinsize
+
 # Disable features that are already covered by statusline and apply compatibility fixes
 MAILCHECK=-1
 VIRTUAL_ENV_DISABLE_PROMPT=1
@@ -43,8 +59,46 @@ if [[ -z "$_sl_prompt_command_registered" ]]; then
     _sl_prompt_command_registered=1
 fi
 
-PS0='${SHELL:0:0$((_sl_start=$(_sl_stamp), 0))}'
-PS1='$("<exec>" run --mode "$_sl_mode" --return-code "$?" --jobs-count "\j" --elapsed-time "$_sl_elapsed" --control-fd 3 3<&$_sl_control_fd &)'
+# Show the command about to run in title, if the template asks for it
+_sl_command_title() {
+    if [[ "$PS1_TITLE" == *'{cmd}'* ]]; then
+        "<exec>" title --command "$(fc -ln -0 2>/dev/null)"
+    fi
+}
+
+# Collapse the prompt of the command about to run into one line, if asked to. Takes `$?` and `\j`
+# which are seen in `PS0` only
+_sl_transient() {
+    if [[ -n "$PS1_TRANSIENT" ]]; then
+        # Stop redrawing the prompt which is collapsed
+        echo >&$_sl_control_fd
+        "<exec>" transient --mode "$_sl_mode" --return-code "$1" --jobs-count "$2" \
+            --elapsed-time "$_sl_elapsed" --command "$(fc -ln -0 2>/dev/null)"
+    fi
+}
+
+# Redraw lines above the prompt when terminal is resized, as they are aligned to its width. Traps
+# of signals which came while a command was running are run after it, when the prompt is gone
+_sl_redraw() {
+    if ((_sl_at_prompt && COLUMNS != _sl_columns)); then
+        echo >&$_sl_control_fd
+        ("<exec>" run --mode "$_sl_mode" --return-code "$_sl_return_code" \
+            --jobs-count "$_sl_jobs" --elapsed-time "$_sl_elapsed" --redraw "$_sl_columns" \
+            --control-fd 3 3<&$_sl_control_fd &)
+        _sl_columns=$COLUMNS
+    fi
+}
+trap '_sl_redraw' WINCH
+
+# Mark start of command output with OSC 133, the other marks are drawn by `run`. Arithmetic
+# expansions set variables in the shell itself, unlike command substitutions
+PS0='$(_sl_transient "$?" "\j")${SHELL:0:0$((_sl_start=$(_sl_stamp), _sl_at_prompt=0, 0))}$(_sl_command_title)\e]133;C\e\\'
+PS1='${SHELL:0:0$((_sl_return_code=$?, _sl_jobs=\j, _sl_columns=COLUMNS, _sl_at_prompt=1, 0))}$("<exec>" run --mode "$_sl_mode" --return-code "$_sl_return_code" --jobs-count "$_sl_jobs" --elapsed-time "$_sl_elapsed" --control-fd 3 3<&$_sl_control_fd &)'
+
+# Keep git data warm in a per-user daemon if asked to. Prompt works the same way without it
+if [[ -n "$PS1_DAEMON" && -n "$XDG_RUNTIME_DIR" ]]; then
+    ("<exec>" daemon >/dev/null 2>&1 &)
+fi
 
 # Nice features
 alias ssh='WORKGROUP_CHAIN="$("<exec>" chain)" ssh -o "SendEnv=WORKGROUP_CHAIN"'
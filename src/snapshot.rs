@@ -0,0 +1,501 @@
+//! Golden tests for the whole statusline, run `UPDATE_SNAPSHOTS=1 cargo test` to accept changes
+
+use crate::{
+    Environment, IconMode, Output, Statusline, file,
+    icon::{self, IconSet},
+    palette::{self, Palette},
+    powerline::{self, Separator},
+    render,
+    style::{self, ColorDepth},
+    sysroot,
+    theme::{self, Background, Theme},
+    width,
+};
+use chrono::{FixedOffset, TimeZone as _};
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    fmt::Write as _,
+    path::{Path, PathBuf},
+    process::Command,
+    time::Duration,
+};
+
+/// Statusline output, split by where it goes
+#[derive(Default)]
+struct Recorder {
+    terminal: String,
+    prompt: String,
+}
+
+impl Output for Recorder {
+    fn draw(&mut self, s: &str) {
+        self.terminal.push_str(s);
+    }
+
+    fn prompt(&mut self, s: &str) {
+        self.prompt.push_str(s);
+    }
+}
+
+struct Fixture {
+    name: &'static str,
+    files: &'static [(&'static str, &'static str)],
+    /// Working directory, relative to the fixture root
+    cwd: &'static str,
+    /// Initialize git repository here, with one commit pushed and one not
+    git: Option<&'static str>,
+    /// Home directory, relative to the fixture root, and whose it is
+    home: (&'static str, &'static str),
+    /// Environment variables, with `<root>` standing for the fixture root
+    vars: &'static [(&'static str, &'static str)],
+    ret_code: Option<u8>,
+    jobs_count: Option<usize>,
+    elapsed_time: Option<Duration>,
+    is_root: bool,
+    terminal_width: usize,
+}
+
+const BASE: Fixture = Fixture {
+    name: "",
+    files: &[
+        ("etc/machine-info", "CHASSIS=laptop\n"),
+        ("home/alice/", ""),
+    ],
+    cwd: "home/alice",
+    git: None,
+    home: ("home/alice", ""),
+    vars: &[],
+    ret_code: Some(0),
+    jobs_count: Some(0),
+    elapsed_time: None,
+    is_root: false,
+    terminal_width: 100,
+};
+
+/// User theme, to check that roles are overridden on top of `base`
+const CUSTOM_THEME: &str = r##"
+base = "solarized"  # the rest is solarized
+
+[git.dirty]
+color = "#ff8800"
+style = "bold,italic"
+
+["return.fail"]
+color = 'bright-red'
+
+[host]
+style = ""
+"##;
+
+const CUSTOM_ICONS: &str = r#"
+base = "text"  # ASCII, without spaces
+user = "@"
+ellipsis = "~"
+continuation = "-->"
+
+[chassis]
+laptop = "L"
+server = "S"
+
+[git]
+branch = "br:"
+ahead = ">"
+behind = "<"
+"#;
+
+const GIT_FILES: &[(&str, &str)] = &[
+    ("etc/machine-info", "CHASSIS=laptop\n"),
+    ("home/alice/project/Cargo.toml", "[package]\n"),
+    ("home/alice/project/src/main.rs", "fn main() {}\n"),
+];
+
+const FIXTURES: &[Fixture] = &[
+    Fixture {
+        name: "home",
+        ..BASE
+    },
+    Fixture {
+        name: "git-repo",
+        files: GIT_FILES,
+        cwd: "home/alice/project/src",
+        git: Some("home/alice/project"),
+        terminal_width: 120,
+        ..BASE
+    },
+    Fixture {
+        name: "three-lines",
+        files: GIT_FILES,
+        cwd: "home/alice/project/src",
+        git: Some("home/alice/project"),
+        terminal_width: 60,
+        ..BASE
+    },
+    Fixture {
+        name: "narrow",
+        files: &[
+            ("etc/machine-info", "CHASSIS=laptop\n"),
+            ("home/alice/deployments/.config/services/frontend/", ""),
+        ],
+        cwd: "home/alice/deployments/.config/services/frontend",
+        terminal_width: 30,
+        ..BASE
+    },
+    Fixture {
+        name: "unique-workdir",
+        files: &[
+            ("etc/machine-info", "CHASSIS=laptop\n"),
+            ("home/alice/work/services/api/Cargo.toml", "[package]\n"),
+            ("home/alice/work/services/api/src/main.rs", "fn main() {}\n"),
+            ("home/alice/work/scripts/", ""),
+            ("home/alice/.local/share/", ""),
+        ],
+        cwd: "home/alice/work/services/api/src",
+        git: Some("home/alice/work/services/api"),
+        vars: &[
+            ("PS1_WORKDIR", "unique"),
+            ("PS1_TITLE", "{repo} on {git_branch} {unknown}{cmd}"),
+        ],
+        ..BASE
+    },
+    Fixture {
+        name: "everything",
+        files: &[
+            ("etc/machine-info", "CHASSIS=server\n"),
+            (
+                "srv/venvs/tools/pyvenv.cfg",
+                "home = /usr/bin\nversion = 3.12.4\n",
+            ),
+            ("srv/mail/new/1718900000.M1P1.box", ""),
+            ("srv/mail/cur/1718800000.M1P1.box:2,S", ""),
+            ("srv/mail/cur/1718700000.M1P1.box:2,", ""),
+            ("home/bob/data/", ""),
+        ],
+        cwd: "home/bob/data",
+        home: ("home/bob", "bob"),
+        vars: &[
+            ("SHLVL", "2"),
+            ("MAIL", "<root>/srv/mail"),
+            ("VIRTUAL_ENV", "<root>/srv/venvs/tools"),
+            ("SSH_CONNECTION", "192.0.2.7 52144 192.0.2.1 22"),
+            ("IN_NIX_SHELL", "impure"),
+            (
+                "buildInputs",
+                "/nix/store/0c8ha4nr3pvw9ab0g2cf3x8i0cw4dkvl-openssl-3.0.14",
+            ),
+        ],
+        ret_code: Some(130),
+        jobs_count: Some(2),
+        elapsed_time: Some(Duration::from_millis(75_300)),
+        is_root: true,
+        ..BASE
+    },
+];
+
+fn git(dir: &Path, args: &[&str]) {
+    let status = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .env("GIT_CONFIG_GLOBAL", "/dev/null")
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .env("GIT_AUTHOR_NAME", "Alice")
+        .env("GIT_AUTHOR_EMAIL", "alice@example.org")
+        .env("GIT_AUTHOR_DATE", "2024-06-20T12:00:00Z")
+        .env("GIT_COMMITTER_NAME", "Alice")
+        .env("GIT_COMMITTER_EMAIL", "alice@example.org")
+        .env("GIT_COMMITTER_DATE", "2024-06-20T12:00:00Z")
+        .status()
+        .expect("git should be installed");
+    assert!(status.success(), "git {args:?} failed");
+}
+
+/// Repository on branch `main` of GitHub remote, one commit ahead of `origin/main`, with staged,
+/// dirty and untracked files
+fn init_repo(tree: &Path) {
+    git(tree, &["init", "-q", "-b", "main"]);
+    git(tree, &["add", "Cargo.toml"]);
+    git(tree, &["commit", "-q", "-m", "Initial commit"]);
+    git(tree, &["update-ref", "refs/remotes/origin/main", "HEAD"]);
+    git(tree, &["config", "branch.main.remote", "origin"]);
+    git(tree, &["config", "branch.main.merge", "refs/heads/main"]);
+    git(
+        tree,
+        &[
+            "config",
+            "remote.origin.url",
+            "git@github.com:alice/project.git",
+        ],
+    );
+    git(tree, &["add", "src/main.rs"]);
+    git(tree, &["commit", "-q", "-m", "Add main"]);
+    std::fs::write(tree.join("src/main.rs"), "fn main() { todo!() }\n").unwrap();
+    std::fs::write(tree.join("README.md"), "# project\n").unwrap();
+    std::fs::write(tree.join("src/lib.rs"), "").unwrap();
+    git(tree, &["add", "src/lib.rs"]);
+}
+
+/// Environment of the fixture, in a root of its own for every `concern` tested in parallel
+fn environment(fixture: &Fixture, concern: &str) -> Environment {
+    let sysroot = sysroot::fixture(&format!("{}-{concern}", fixture.name), None, fixture.files);
+    let root = sysroot.path("/");
+    if let Some(tree) = fixture.git {
+        init_repo(&root.join(tree));
+    }
+
+    let work_dir = root.join(fixture.cwd);
+    let git_tree = file::upfind(&work_dir, ".git").map(|dg| dg.parent().unwrap().to_path_buf());
+    let (home, home_user) = fixture.home;
+
+    let mut vars: HashMap<OsString, OsString> = fixture
+        .vars
+        .iter()
+        .map(|(name, value)| {
+            let value = value.replace("<root>", root.to_str().unwrap());
+            (name.into(), value.into())
+        })
+        .collect();
+    vars.insert("PWD".into(), work_dir.clone().into());
+    vars.insert("HOME".into(), root.join(home).into());
+    // Git run by blocks sees only these, and not configuration of whoever runs tests
+    vars.insert("GIT_CONFIG_GLOBAL".into(), "/dev/null".into());
+    vars.insert("GIT_CONFIG_NOSYSTEM".into(), "1".into());
+    if let Some(path) = std::env::var_os("PATH") {
+        vars.insert("PATH".into(), path);
+    }
+
+    Environment {
+        ret_code: fixture.ret_code,
+        jobs_count: fixture.jobs_count,
+        elapsed_time: fixture.elapsed_time,
+        cwd: work_dir.clone(),
+        work_dir,
+        git_tree,
+        user: if fixture.is_root { "root" } else { "alice" }.to_owned(),
+        host: "box".to_owned(),
+        current_home: Some((root.join(home), home_user.to_owned())),
+        sysroot,
+        is_root: fixture.is_root,
+        now: FixedOffset::east_opt(3 * 3600)
+            .unwrap()
+            .with_ymd_and_hms(2024, 6, 20, 15, 4, 5)
+            .unwrap(),
+        vars,
+    }
+}
+
+/// Makes escape sequences visible, keeping the snapshot reversible
+fn escape(s: &str) -> String {
+    let mut res = String::new();
+    for c in s.chars() {
+        match c {
+            '\\' => res.push_str("\\\\"),
+            '\x1b' => res.push_str("\\e"),
+            '\x07' => res.push_str("\\a"),
+            '\n' => res.push_str("\\n\n"),
+            _ if c.is_control() => write!(res, "\\x{:02x}", u32::from(c)).unwrap(),
+            _ => res.push(c),
+        }
+    }
+    res
+}
+
+fn snapshot_path(name: &str, mode: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/snapshots")
+        .join(format!("{name}.{mode}.txt"))
+}
+
+fn check(path: &Path, actual: &str, mismatched: &mut Vec<PathBuf>) {
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, actual).unwrap();
+    } else if std::fs::read_to_string(path).ok().as_deref() != Some(actual) {
+        eprintln!("{}:\n{actual}", path.display());
+        mismatched.push(path.to_owned());
+    }
+}
+
+/// All blocks in each built-in theme, color depth, palette and segment style, and with custom icons
+fn styled(environ: &Environment) -> String {
+    let all_blocks: Vec<&str> = [crate::LEFT, crate::MIDDLE, crate::RIGHT, crate::BOTTOM].concat();
+    let mut themed = String::new();
+    for name in Theme::builtin_names() {
+        theme::set(Theme::builtin(name).unwrap());
+        let line = crate::render_line(&all_blocks, environ, IconMode::Icons, &render::Ansi);
+        writeln!(themed, "--- {name}\n{}", escape(&line)).unwrap();
+    }
+    theme::set(Theme::parse(CUSTOM_THEME, Background::Dark).unwrap());
+    let custom = crate::render_line(&all_blocks, environ, IconMode::Icons, &render::Ansi);
+    writeln!(themed, "--- custom\n{}", escape(&custom)).unwrap();
+    theme::set(Theme::default());
+    for depth in [ColorDepth::Ansi256, ColorDepth::Ansi16, ColorDepth::None] {
+        style::set_depth(depth);
+        let line = crate::render_line(&all_blocks, environ, IconMode::Icons, &render::Ansi);
+        writeln!(themed, "--- dark, {}\n{}", depth.as_str(), escape(&line)).unwrap();
+    }
+    style::set_depth(ColorDepth::True);
+    let mapping = environ.sysroot.path("/colors.toml");
+    std::fs::write(&mapping, "alice = \"bright-green\"\n").unwrap();
+    for name in Palette::names() {
+        palette::set(
+            Palette::load(|var| match var {
+                "PS1_HASH_PALETTE" => Some(name.to_owned()),
+                "PS1_COLORS" => Some(mapping.to_str().unwrap().to_owned()),
+                _ => None,
+            })
+            .unwrap(),
+        );
+        let line = crate::render_line(&all_blocks, environ, IconMode::Icons, &render::Ansi);
+        writeln!(themed, "--- dark, {name} palette\n{}", escape(&line)).unwrap();
+    }
+    palette::set(Palette::default());
+    for (name, mode) in [
+        ("plain", IconMode::Icons),
+        ("rounded", IconMode::Icons),
+        ("slanted", IconMode::MinimalIcons),
+        ("slanted", IconMode::Text),
+    ] {
+        powerline::set(Separator::from_name(Some(name)));
+        let line = crate::render_line(&all_blocks, environ, mode, &render::Ansi);
+        writeln!(themed, "--- dark, {name} segments\n{}", escape(&line)).unwrap();
+    }
+    powerline::set(Separator::Brackets);
+    let icons = IconSet::parse(CUSTOM_ICONS).unwrap();
+    let mode = icons.base();
+    icon::set(icons);
+    let line = crate::render_line(&all_blocks, environ, mode, &render::Ansi);
+    writeln!(themed, "--- dark, custom icons\n{}", escape(&line)).unwrap();
+    icon::set(IconSet::parse("").unwrap());
+    themed
+}
+
+/// Resets settings kept in thread-locals on creation and drop, so that they do not leak between
+/// snapshots even if one of them panics
+struct Settings;
+
+impl Settings {
+    fn reset() -> Self {
+        Self::restore();
+        Settings
+    }
+
+    fn restore() {
+        theme::set(Theme::default());
+        palette::set(Palette::default());
+        style::set_depth(ColorDepth::True);
+        powerline::set(Separator::Brackets);
+        width::set_ambiguous_wide(false);
+        width::set_nerd_wide(false);
+        icon::set(IconSet::parse("").unwrap());
+        render::set(&render::Readline);
+    }
+}
+
+impl Drop for Settings {
+    fn drop(&mut self) {
+        Self::restore();
+    }
+}
+
+/// Compares snapshots which `make` takes of every fixture, by their kinds
+fn snapshots(concern: &str, make: impl Fn(&Fixture, &Environment) -> Vec<(&'static str, String)>) {
+    let mut mismatched = vec![];
+    for fixture in FIXTURES {
+        let _settings = Settings::reset();
+        let environ = environment(fixture, concern);
+        // Fixture root differs between runs, and is seen in links and JSON
+        let root = environ.sysroot.path("/");
+        let root = root.to_str().unwrap().trim_end_matches('/');
+        for (kind, actual) in make(fixture, &environ) {
+            check(
+                &snapshot_path(fixture.name, kind),
+                &actual.replace(root, "<root>"),
+                &mut mismatched,
+            );
+        }
+        drop(std::fs::remove_dir_all(environ.sysroot.path("/")));
+    }
+    assert!(
+        mismatched.is_empty(),
+        "snapshots differ, rerun with UPDATE_SNAPSHOTS=1 if this is intended: {mismatched:?}"
+    );
+}
+
+#[test]
+fn modes() {
+    snapshots("modes", |fixture, environ| {
+        let take = |mode| {
+            let mut recorder = Recorder::default();
+            Statusline::new(environ).print(mode, environ, fixture.terminal_width, &mut recorder);
+            let mut resized = Recorder::default();
+            Statusline::new(environ).redraw(
+                mode,
+                fixture.terminal_width,
+                fixture.terminal_width * 2 / 3,
+                &mut resized,
+            );
+            let collapsed = Statusline::new(environ).collapse(
+                mode,
+                environ,
+                fixture.terminal_width,
+                "cargo build --release",
+            );
+            format!(
+                "--- terminal\n{}\n--- prompt\n{}\n--- resized\n{}\n--- transient\n{}\n",
+                escape(&recorder.terminal),
+                escape(&recorder.prompt),
+                escape(&resized.terminal),
+                escape(&collapsed)
+            )
+        };
+        let mut modes: Vec<_> = [
+            ("text", IconMode::Text),
+            ("icons", IconMode::Icons),
+            ("minimal", IconMode::MinimalIcons),
+        ]
+        .into_iter()
+        .map(|(kind, mode)| (kind, take(mode)))
+        .collect();
+        let icons = IconSet::parse(CUSTOM_ICONS).unwrap();
+        let mode = icons.base();
+        icon::set(icons);
+        modes.push(("custom", take(mode)));
+        modes
+    });
+}
+
+#[test]
+fn render_backends() {
+    snapshots("render", |_, environ| {
+        let line_blocks: Vec<&str> = crate::LINE_BLOCKS.split(',').collect();
+        let mut lines = String::new();
+        for name in render::names() {
+            let backend = render::by_name(name).unwrap();
+            let line = crate::render_line(&line_blocks, environ, IconMode::Icons, backend);
+            writeln!(lines, "--- {name}\n{}", escape(&line)).unwrap();
+        }
+        vec![("render", lines)]
+    });
+}
+
+#[test]
+fn themes() {
+    snapshots("themes", |_, environ| vec![("themes", styled(environ))]);
+}
+
+#[test]
+fn bar() {
+    snapshots("bar", |_, environ| {
+        let bar_blocks: Vec<&str> = crate::BAR_BLOCKS.split(',').collect();
+        let bar = crate::bar::status(&bar_blocks, environ, IconMode::Icons);
+        vec![("bar", format!("{bar}\n"))]
+    });
+}
+
+#[test]
+fn json() {
+    snapshots("json", |_, environ| {
+        vec![("json", format!("{}\n", crate::blocks_json(environ)))]
+    });
+}
@@ -1,14 +1,22 @@
+use crate::{
+    powerline, render,
+    theme::{self, Role},
+};
 use bitflags::bitflags;
 use const_format::formatcp;
-use std::fmt::{Display, Formatter, Result as FmtResult, Write};
+use std::{
+    cell::Cell,
+    fmt::{Display, Formatter, Result as FmtResult, Write},
+};
 
-const INVISIBLE_START: &str = "\x01";
-const INVISIBLE_END: &str = "\x02";
+pub const INVISIBLE_START: &str = "\x01";
+pub const INVISIBLE_END: &str = "\x02";
 const BEL: &str = "\x07";
 const ESC: &str = "\x1b";
-const CSI: &str = formatcp!("{ESC}[");
+pub const CSI: &str = formatcp!("{ESC}[");
 const OSC: &str = formatcp!("{ESC}]");
-const RESET: &str = formatcp!("{CSI}0m");
+const ST: &str = formatcp!("{ESC}\\");
+pub const RESET: &str = formatcp!("{CSI}0m");
 const CURSOR_SAVE: &str = formatcp!("{CSI}s");
 const CURSOR_RESTORE: &str = formatcp!("{CSI}u");
 const CLEAR_LINE: &str = formatcp!("{CSI}0K");
@@ -23,17 +31,63 @@ pub fn prologue(three_line_mode: bool) -> String {
         prev_line(if three_line_mode { 2 } else { 1 })
     )
 }
+/// Clears the rest of the line, which may be left from a longer line drawn before
+pub fn clear_line() -> String {
+    format!("{INVISIBLE_START}{CLEAR_LINE}{INVISIBLE_END}")
+}
 pub fn epilogue() -> String {
     format!("{INVISIBLE_START}{CURSOR_RESTORE}{INVISIBLE_END}")
 }
 pub fn title(title: &str) -> String {
     format!("{INVISIBLE_START}{OSC}0;{title}{BEL}{INVISIBLE_END}")
 }
-pub fn horizontal_absolute(n: usize) -> String {
-    format!("{INVISIBLE_START}{CSI}{n}G{INVISIBLE_END}")
+/// Replaces `lines` lines, the first of which is `rows` above the cursor, with one `line`. Lines
+/// below move up, and the cursor moves with them
+pub fn collapse(line: &str, rows: usize, lines: usize) -> String {
+    let mut res = format!(
+        "{INVISIBLE_START}{}{CLEAR_LINE}{INVISIBLE_END}{line}{INVISIBLE_START}{CSI}1B",
+        prev_line(rows)
+    );
+    if lines > 1 {
+        write!(res, "{CSI}{}M", lines - 1).unwrap();
+    }
+    write!(res, "{CSI}{}B{CSI}G{INVISIBLE_END}", rows - lines).unwrap();
+    res
+}
+/// Marks the end of the last command's output with its exit status, for OSC 133 aware terminals
+pub fn command_finished(code: Option<u8>) -> String {
+    let code = code.map(|code| format!(";{code}")).unwrap_or_default();
+    format!("{INVISIBLE_START}{OSC}133;D{code}{ST}{INVISIBLE_END}")
+}
+/// Marks where the prompt starts, so that the terminal can jump between prompts
+pub fn prompt_start() -> String {
+    format!("{INVISIBLE_START}{OSC}133;A{ST}{INVISIBLE_END}")
+}
+/// Marks where the prompt ends and the command starts
+pub fn prompt_end() -> String {
+    format!("{INVISIBLE_START}{OSC}133;B{ST}{INVISIBLE_END}")
+}
+/// Tells the terminal working directory as `file://` URL with OSC 7, for opening new tabs there
+pub fn current_dir(url: &str) -> String {
+    format!("{INVISIBLE_START}{OSC}7;{url}{ST}{INVISIBLE_END}")
+}
+/// Starts OSC 8 hyperlink to `url`, or ends it if `url` is empty
+pub fn hyperlink(url: &str) -> String {
+    format!("{OSC}8;;{url}{ST}")
+}
+/// Moves cursor so that `width` columns of text end at the right edge, counting from the edge
+/// itself so that the width of the terminal does not matter
+pub fn right_aligned(width: usize) -> String {
+    let back = if width == 0 {
+        String::new()
+    } else {
+        format!("{CSI}{width}D")
+    };
+    format!("{INVISIBLE_START}{CSI}999C{back}{INVISIBLE_END}")
 }
 
 bitflags! {
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
     pub struct Style: u8 {
         const BOLD = 0x01;
         const ITALIC = 0x02;
@@ -52,6 +106,7 @@ impl Display for Style {
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Color {
     // CSI 30 + {}
     Low8(u8),
@@ -60,16 +115,145 @@ pub enum Color {
     True(u8, u8, u8),
 }
 
-impl Display for Color {
-    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+/// How many colors terminal can show
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum ColorDepth {
+    None,
+    Ansi16,
+    Ansi256,
+    True,
+}
+
+impl ColorDepth {
+    pub fn as_str(self) -> &'static str {
         match self {
-            Self::Low8(low) => write!(f, "{CSI}{}m", 30 + low),
-            Self::High8(high) => write!(f, "{CSI}{}m", 90 + high),
-            Self::True(r, g, b) => write!(f, "{CSI}38;2;{r};{g};{b}m"),
+            Self::None => "none",
+            Self::Ansi16 => "16 colors",
+            Self::Ansi256 => "256 colors",
+            Self::True => "truecolor",
         }
     }
 }
 
+thread_local! {
+    static DEPTH: Cell<ColorDepth> = const { Cell::new(ColorDepth::True) };
+}
+
+/// Sets color depth which colors are downgraded to on this thread
+pub fn set_depth(depth: ColorDepth) {
+    DEPTH.set(depth);
+}
+
+/// Color depth which colors are downgraded to on this thread
+pub fn depth() -> ColorDepth {
+    DEPTH.get()
+}
+
+impl Color {
+    /// Writes SGR for this color, `base` is 30 for foreground and 40 for background
+    fn write_sgr(self, f: &mut Formatter<'_>, base: u8) -> FmtResult {
+        match (self, DEPTH.get()) {
+            (_, ColorDepth::None) => Ok(()),
+            (Self::Low8(low), _) => write!(f, "{CSI}{}m", base + low),
+            (Self::High8(high), _) => write!(f, "{CSI}{}m", base + 60 + high),
+            (Self::True(r, g, b), ColorDepth::True) => {
+                write!(f, "{CSI}{};2;{r};{g};{b}m", base + 8)
+            }
+            (Self::True(r, g, b), ColorDepth::Ansi256) => {
+                write!(f, "{CSI}{};5;{}m", base + 8, nearest_256((r, g, b)))
+            }
+            (Self::True(r, g, b), ColorDepth::Ansi16) => match nearest_16((r, g, b)) {
+                low @ 0..8 => write!(f, "{CSI}{}m", base + low),
+                high => write!(f, "{CSI}{}m", base + 60 + high - 8),
+            },
+        }
+    }
+
+    /// Same color used as background
+    pub fn background(self) -> BackgroundColor {
+        BackgroundColor(self)
+    }
+}
+
+impl Display for Color {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        self.write_sgr(f, 30)
+    }
+}
+
+/// Color displayed as background, made by [`Color::background`]
+#[derive(Clone, Copy)]
+pub struct BackgroundColor(Color);
+
+impl Display for BackgroundColor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        self.0.write_sgr(f, 40)
+    }
+}
+
+/// 16 basic colors as in xterm
+const XTERM: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// Levels of 6x6x6 color cube in 256-color palette
+const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// Squared distance between colors in RGB space
+pub fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    [(r1, r2), (g1, g2), (b1, b2)]
+        .into_iter()
+        .map(|(one, other)| u32::from(one.abs_diff(other)).pow(2))
+        .sum()
+}
+
+fn nearest_16(rgb: (u8, u8, u8)) -> u8 {
+    (0..16)
+        .min_by_key(|&idx| distance(rgb, XTERM[usize::from(idx)]))
+        .unwrap_or_default()
+}
+
+/// Closest of color cube and grayscale ramp, basic colors are left out as terminals redefine them
+fn nearest_256(rgb: (u8, u8, u8)) -> u8 {
+    let level = |channel: u8| {
+        (0..6)
+            .min_by_key(|&idx| CUBE[usize::from(idx)].abs_diff(channel))
+            .unwrap_or_default()
+    };
+    let (r, g, b) = (level(rgb.0), level(rgb.1), level(rgb.2));
+    let cube = (
+        16 + 36 * r + 6 * g + b,
+        (
+            CUBE[usize::from(r)],
+            CUBE[usize::from(g)],
+            CUBE[usize::from(b)],
+        ),
+    );
+    let gray = (0..24)
+        .map(|idx| (232 + idx, (8 + 10 * idx, 8 + 10 * idx, 8 + 10 * idx)))
+        .min_by_key(|&(_, shade)| distance(rgb, shade))
+        .unwrap_or(cube);
+    [cube, gray]
+        .into_iter()
+        .min_by_key(|&(_, candidate)| distance(rgb, candidate))
+        .map_or(cube.0, |(idx, _)| idx)
+}
+
 impl Color {
     pub const RED: Self = Self::Low8(1);
     pub const GREEN: Self = Self::Low8(2);
@@ -87,62 +271,120 @@ impl Color {
     pub const GRAY: Self = Self::True(128, 128, 128);
     pub const TRUE_YELLOW: Self = Self::True(255, 170, 0);
 
-    pub fn of(what: &str) -> Self {
-        fn polyhash(s: &str, m: usize, p: usize, h_init: usize) -> usize {
-            let mut h = h_init % m;
-            for by in s.bytes() {
-                h = (h * p + by as usize) % m;
-            }
-            h
-        }
+    /// Color as `#rrggbb`, with 16 basic colors as in xterm
+    pub fn hex(self) -> String {
+        let (r, g, b) = self.rgb();
+        format!("#{r:02x}{g:02x}{b:02x}")
+    }
 
-        if what == "root" {
-            Self::RED
+    /// `red`, `bright-red` and other basic colors, or `#rrggbb`
+    pub fn parse(value: &str) -> Option<Self> {
+        const NAMES: [&str; 8] = [
+            "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+        ];
+        if let Some(hex) = value.strip_prefix('#') {
+            let rgb = u32::from_str_radix(hex, 16)
+                .ok()
+                .filter(|_| hex.len() == 6)?;
+            let [_, r, g, b] = rgb.to_be_bytes();
+            return Some(Self::True(r, g, b));
+        }
+        let (name, bright) = match value.strip_prefix("bright-") {
+            Some(name) => (name, true),
+            None => (value, false),
+        };
+        let idx = NAMES.iter().position(|&known| known == name)?;
+        let idx = u8::try_from(idx).ok()?;
+        Some(if bright {
+            Self::High8(idx)
         } else {
-            let idx = polyhash(what, 23, 179, what.len()) + 1;
-            let (r, g, b) = HSV_COLOR_TABLE[idx];
-            Self::True(r, g, b)
+            Self::Low8(idx)
+        })
+    }
+
+    /// Same color as 24-bit RGB
+    pub fn rgb(self) -> (u8, u8, u8) {
+        match self {
+            Self::Low8(low) => XTERM[usize::from(low % 8)],
+            Self::High8(high) => XTERM[8 + usize::from(high % 8)],
+            Self::True(r, g, b) => (r, g, b),
         }
     }
 }
 
-const HSV_COLOR_TABLE: [(u8, u8, u8); 24] = [
-    (255, 0, 0),
-    (255, 85, 0),
-    (255, 128, 0),
-    (255, 170, 0),
-    (255, 213, 0),
-    (255, 255, 0),
-    (213, 255, 0),
-    (170, 255, 0),
-    (128, 255, 0),
-    (0, 255, 85),
-    (0, 255, 128),
-    (0, 255, 170),
-    (0, 255, 213),
-    (0, 213, 255),
-    (0, 128, 255),
-    (0, 85, 255),
-    (128, 0, 255),
-    (170, 0, 255),
-    (213, 0, 255),
-    (255, 0, 255),
-    (255, 0, 212),
-    (255, 0, 170),
-    (255, 0, 128),
-    (255, 0, 85),
-];
-
 pub trait WithStyle: Write {
     fn with_style<F>(&mut self, color: Color, style: Style, func: F) -> FmtResult
     where
         F: FnOnce(&mut Self) -> FmtResult,
     {
-        write!(self, "{INVISIBLE_START}{color}{style}{INVISIBLE_END}")?;
+        // Markup is always marked invisible, backends rewrite it in `RenderBackend::finish`
+        let backend = render::current();
+        let start = powerline::styled(color, style).unwrap_or_else(|| backend.start(color, style));
+        write!(self, "{INVISIBLE_START}{start}{INVISIBLE_END}")?;
         func(self)?;
-        write!(self, "{INVISIBLE_START}{RESET}{INVISIBLE_END}")?;
+        write!(
+            self,
+            "{INVISIBLE_START}{}{}{INVISIBLE_END}",
+            backend.end(),
+            powerline::restore().unwrap_or_default()
+        )?;
         Ok(())
     }
+
+    /// Makes text a hyperlink to `url`, if there is one and the backend can show it
+    fn with_link<F>(&mut self, url: Option<&str>, func: F) -> FmtResult
+    where
+        F: FnOnce(&mut Self) -> FmtResult,
+    {
+        let backend = render::current();
+        let (start, end) = url.map_or_else(Default::default, |url| {
+            (backend.link_start(url), backend.link_end())
+        });
+        if !start.is_empty() {
+            write!(self, "{INVISIBLE_START}{start}{INVISIBLE_END}")?;
+        }
+        func(self)?;
+        if !end.is_empty() {
+            write!(self, "{INVISIBLE_START}{end}{INVISIBLE_END}")?;
+        }
+        Ok(())
+    }
+
+    /// Styles text as the current theme says for `role`
+    fn with_role<F>(&mut self, role: Role, func: F) -> FmtResult
+    where
+        F: FnOnce(&mut Self) -> FmtResult,
+    {
+        self.with_role_of(role, "", func)
+    }
+
+    /// Same as `with_role`, but hashed colors are derived from `what`
+    fn with_role_of<F>(&mut self, role: Role, what: &str, func: F) -> FmtResult
+    where
+        F: FnOnce(&mut Self) -> FmtResult,
+    {
+        let (color, style) = theme::paint(role, what);
+        self.with_style(color, style, func)
+    }
+
+    /// Draws text which makes a block on its own: in brackets colored for `role`, or as a
+    /// segment on background of that color
+    fn segment<F>(&mut self, role: Role, what: &str, func: F) -> FmtResult
+    where
+        F: FnOnce(&mut Self) -> FmtResult,
+    {
+        let (color, style) = theme::paint(role, what);
+        if let Some(start) = powerline::start(color) {
+            self.write_str(&start)?;
+            func(self)?;
+            return self.write_char(' ');
+        }
+        self.with_style(color, style, |f| {
+            f.write_char('[')?;
+            func(f)?;
+            f.write_char(']')
+        })
+    }
 }
 
 impl<T: Write> WithStyle for T {}
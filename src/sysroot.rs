@@ -0,0 +1,87 @@
+use std::{
+    cell::OnceCell,
+    path::{Path, PathBuf},
+};
+
+/// What CPUID tells about the machine
+#[derive(Debug)]
+pub struct Cpuid {
+    /// CPU vendor from leaf 0, which is the physical one even in virtual machines
+    pub vendor: String,
+    /// Hypervisor signature from leaf 0x40000000, if the hypervisor bit of leaf 1 is set
+    pub hypervisor: Option<String>,
+}
+
+/// Root of the filesystem which system information is read from, `/` outside of tests
+pub struct Sysroot {
+    root: PathBuf,
+    /// CPUID, which can not be read from files
+    cpuid: OnceCell<Option<Cpuid>>,
+}
+
+impl Sysroot {
+    pub fn host() -> Self {
+        Sysroot {
+            root: PathBuf::from("/"),
+            cpuid: OnceCell::new(),
+        }
+    }
+
+    /// Resolves absolute path like `/proc/cpuinfo` against this root
+    pub fn path<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.root
+            .join(path.as_ref().strip_prefix("/").unwrap_or(path.as_ref()))
+    }
+
+    /// CPUID, `None` on architectures which do not have it
+    pub fn cpuid(&self) -> Option<&Cpuid> {
+        self.cpuid.get_or_init(query_cpuid).as_ref()
+    }
+}
+
+#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+fn query_cpuid() -> Option<Cpuid> {
+    let cpuid = raw_cpuid::CpuId::new();
+    let vendor = cpuid.get_vendor_info()?.as_str().to_owned();
+    let hypervisor = cpuid
+        .get_feature_info()
+        .filter(raw_cpuid::FeatureInfo::has_hypervisor)
+        .map(|_| {
+            let leaf = raw_cpuid::cpuid!(0x4000_0000);
+            let signature: Vec<u8> = [leaf.ebx, leaf.ecx, leaf.edx]
+                .into_iter()
+                .flat_map(u32::to_le_bytes)
+                .collect();
+            // KVM pads its signature with NULs
+            String::from_utf8_lossy(&signature)
+                .trim_end_matches('\0')
+                .to_owned()
+        });
+    Some(Cpuid { vendor, hypervisor })
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "x86")))]
+fn query_cpuid() -> Option<Cpuid> {
+    None
+}
+
+/// Creates a fake root with given files, paths ending with `/` are created as directories
+#[cfg(test)]
+pub fn fixture(name: &str, cpuid: Option<Cpuid>, files: &[(&str, &str)]) -> Sysroot {
+    let root = std::env::temp_dir().join(format!("statusline-test-{}-{name}", std::process::id()));
+    drop(std::fs::remove_dir_all(&root));
+    std::fs::create_dir_all(&root).unwrap();
+    for (path, content) in files {
+        let path = root.join(path);
+        if path.as_os_str().as_encoded_bytes().ends_with(b"/") {
+            std::fs::create_dir_all(path).unwrap();
+        } else {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+    }
+    Sysroot {
+        root,
+        cpuid: OnceCell::from(cpuid),
+    }
+}
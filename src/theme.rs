@@ -0,0 +1,395 @@
+//! Colors and styles of every piece of statusline, by what the piece means
+
+use crate::{
+    config::{self, Scalar},
+    palette,
+    style::{Color, Style},
+};
+use anyhow::{Context as _, Result, bail};
+use std::{cell::RefCell, path::Path};
+
+/// What a piece of statusline means
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Role {
+    Host,
+    User,
+    Container,
+    Ssh,
+    GitHead,
+    GitDirty,
+    Build,
+    Nix,
+    Venv,
+    Jobs,
+    Mail,
+    WorkdirState,
+    WorkdirHome,
+    WorkdirRepo,
+    ReturnOk,
+    ReturnFail,
+    ReturnSignal,
+    ReturnUnavailable,
+    Elapsed,
+    Time,
+    ShellRoot,
+    ShellUser,
+}
+
+impl Role {
+    pub const ALL: [Self; 22] = [
+        Self::Host,
+        Self::User,
+        Self::Container,
+        Self::Ssh,
+        Self::GitHead,
+        Self::GitDirty,
+        Self::Build,
+        Self::Nix,
+        Self::Venv,
+        Self::Jobs,
+        Self::Mail,
+        Self::WorkdirState,
+        Self::WorkdirHome,
+        Self::WorkdirRepo,
+        Self::ReturnOk,
+        Self::ReturnFail,
+        Self::ReturnSignal,
+        Self::ReturnUnavailable,
+        Self::Elapsed,
+        Self::Time,
+        Self::ShellRoot,
+        Self::ShellUser,
+    ];
+
+    /// Name of the table in theme file
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Host => "host",
+            Self::User => "user",
+            Self::Container => "container",
+            Self::Ssh => "ssh",
+            Self::GitHead => "git.head",
+            Self::GitDirty => "git.dirty",
+            Self::Build => "build",
+            Self::Nix => "nix",
+            Self::Venv => "venv",
+            Self::Jobs => "jobs",
+            Self::Mail => "mail",
+            Self::WorkdirState => "workdir.state",
+            Self::WorkdirHome => "workdir.home",
+            Self::WorkdirRepo => "workdir.repo",
+            Self::ReturnOk => "return.ok",
+            Self::ReturnFail => "return.fail",
+            Self::ReturnSignal => "return.signal",
+            Self::ReturnUnavailable => "return.unavailable",
+            Self::Elapsed => "elapsed",
+            Self::Time => "time",
+            Self::ShellRoot => "shell.root",
+            Self::ShellUser => "shell.user",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|role| role.as_str() == name)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Paint {
+    Fixed(Color),
+    /// Color derived from the text itself, like for hostnames
+    Hashed,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Look {
+    pub paint: Paint,
+    pub style: Style,
+}
+
+impl Look {
+    const fn fixed(color: Color, style: Style) -> Self {
+        Look {
+            paint: Paint::Fixed(color),
+            style,
+        }
+    }
+
+    const fn hashed(style: Style) -> Self {
+        Look {
+            paint: Paint::Hashed,
+            style,
+        }
+    }
+}
+
+/// Terminal background, which hashed colors have to stand out from
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Background {
+    Dark,
+    Light,
+}
+
+impl Background {
+    /// Light if relative luminance of the color is above the middle
+    pub fn of((r, g, b): (u8, u8, u8)) -> Self {
+        let luminance = 2126 * u32::from(r) + 7152 * u32::from(g) + 722 * u32::from(b);
+        if luminance > 10000 * 255 / 2 {
+            Self::Light
+        } else {
+            Self::Dark
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Dark => "dark",
+            Self::Light => "light",
+        }
+    }
+
+    /// Cached `PS1_BACKGROUND`, dark if terminal has not answered
+    pub fn from_name(name: Option<&str>) -> Self {
+        match name {
+            Some("light") => Self::Light,
+            _ => Self::Dark,
+        }
+    }
+}
+
+pub struct Theme {
+    looks: Vec<Look>,
+    background: Background,
+}
+
+type Builtin = fn(Role) -> Look;
+
+const BUILTIN: &[(&str, Background, Builtin)] = &[
+    ("dark", Background::Dark, dark),
+    ("light", Background::Light, light),
+    ("solarized", Background::Dark, solarized),
+    ("high-contrast", Background::Dark, high_contrast),
+];
+
+const NONE: Style = Style::empty();
+
+fn dark(role: Role) -> Look {
+    use Role::*;
+    match role {
+        Host | User | Container | GitHead => Look::hashed(Style::BOLD),
+        Ssh | Elapsed | WorkdirRepo => Look::fixed(Color::CYAN, NONE),
+        GitDirty => Look::fixed(Color::PINK, NONE),
+        Build => Look::fixed(Color::PURPLE, NONE),
+        Nix => Look::fixed(Color::BRIGHT_BLUE, NONE),
+        Venv | Mail => Look::fixed(Color::YELLOW, NONE),
+        Jobs => Look::fixed(Color::GREEN, Style::BOLD),
+        WorkdirState => Look::fixed(Color::RED, Style::ITALIC),
+        WorkdirHome => Look::fixed(Color::YELLOW, Style::BOLD),
+        ReturnOk => Look::fixed(Color::LIGHT_GREEN, NONE),
+        ReturnFail => Look::fixed(Color::LIGHT_RED, NONE),
+        ReturnSignal => Look::fixed(Color::TRUE_YELLOW, NONE),
+        ReturnUnavailable => Look::fixed(Color::LIGHT_GRAY, NONE),
+        Time => Look::fixed(Color::GRAY, NONE),
+        ShellRoot => Look::fixed(Color::RED, NONE),
+        ShellUser => Look::fixed(Color::GREEN, NONE),
+    }
+}
+
+/// Darker shades, readable on white
+fn light(role: Role) -> Look {
+    const YELLOW: Color = Color::True(160, 110, 0);
+    const CYAN: Color = Color::True(0, 130, 140);
+
+    use Role::*;
+    match role {
+        Ssh | Elapsed | WorkdirRepo => Look::fixed(CYAN, NONE),
+        GitDirty => Look::fixed(Color::True(190, 0, 120), NONE),
+        Venv | Mail => Look::fixed(YELLOW, NONE),
+        Jobs => Look::fixed(Color::True(0, 130, 0), Style::BOLD),
+        WorkdirHome => Look::fixed(YELLOW, Style::BOLD),
+        ReturnOk => Look::fixed(Color::True(0, 140, 0), NONE),
+        ReturnFail => Look::fixed(Color::True(200, 0, 40), NONE),
+        ReturnSignal => Look::fixed(Color::True(180, 100, 0), NONE),
+        ReturnUnavailable | Time => Look::fixed(Color::True(100, 100, 100), NONE),
+        _ => dark(role),
+    }
+}
+
+fn solarized(role: Role) -> Look {
+    const YELLOW: Color = Color::True(0xb5, 0x89, 0x00);
+    const ORANGE: Color = Color::True(0xcb, 0x4b, 0x16);
+    const RED: Color = Color::True(0xdc, 0x32, 0x2f);
+    const MAGENTA: Color = Color::True(0xd3, 0x36, 0x82);
+    const VIOLET: Color = Color::True(0x6c, 0x71, 0xc4);
+    const BLUE: Color = Color::True(0x26, 0x8b, 0xd2);
+    const CYAN: Color = Color::True(0x2a, 0xa1, 0x98);
+    const GREEN: Color = Color::True(0x85, 0x99, 0x00);
+    const BASE01: Color = Color::True(0x58, 0x6e, 0x75);
+
+    use Role::*;
+    match role {
+        Host | User | Container | GitHead => Look::hashed(Style::BOLD),
+        Ssh | Elapsed | WorkdirRepo => Look::fixed(CYAN, NONE),
+        GitDirty => Look::fixed(MAGENTA, NONE),
+        Build => Look::fixed(VIOLET, NONE),
+        Nix => Look::fixed(BLUE, NONE),
+        Venv | Mail => Look::fixed(YELLOW, NONE),
+        Jobs => Look::fixed(GREEN, Style::BOLD),
+        WorkdirState => Look::fixed(RED, Style::ITALIC),
+        WorkdirHome => Look::fixed(YELLOW, Style::BOLD),
+        ReturnOk | ShellUser => Look::fixed(GREEN, NONE),
+        ReturnFail | ShellRoot => Look::fixed(RED, NONE),
+        ReturnSignal => Look::fixed(ORANGE, NONE),
+        ReturnUnavailable | Time => Look::fixed(BASE01, NONE),
+    }
+}
+
+/// Only bright basic colors, all bold
+fn high_contrast(role: Role) -> Look {
+    use Role::*;
+    let color = match role {
+        Host | User | Container | GitHead => return Look::hashed(Style::BOLD),
+        Ssh | Elapsed | WorkdirRepo => Color::High8(6),
+        GitDirty | Build => Color::High8(5),
+        Nix => Color::High8(4),
+        Venv | Mail | WorkdirHome | ReturnSignal => Color::High8(3),
+        Jobs | ReturnOk | ShellUser => Color::High8(2),
+        WorkdirState | ReturnFail | ShellRoot => Color::High8(1),
+        ReturnUnavailable | Time => Color::High8(7),
+    };
+    Look::fixed(color, Style::BOLD)
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::from_builtin(Background::Dark, dark)
+    }
+}
+
+impl Theme {
+    fn from_builtin(background: Background, builtin: Builtin) -> Self {
+        Theme {
+            looks: Role::ALL.into_iter().map(builtin).collect(),
+            background,
+        }
+    }
+
+    pub fn builtin(name: &str) -> Option<Self> {
+        BUILTIN
+            .iter()
+            .find(|(builtin_name, ..)| *builtin_name == name)
+            .map(|&(_, background, builtin)| Self::from_builtin(background, builtin))
+    }
+
+    pub fn builtin_names() -> impl Iterator<Item = &'static str> {
+        BUILTIN.iter().map(|(name, ..)| *name)
+    }
+
+    /// Default theme for the background
+    pub fn for_background(background: Background) -> Self {
+        Self::builtin(background.as_str()).unwrap_or_default()
+    }
+
+    /// Theme file: top-level `base` names built-in theme to start from, and tables named by roles
+    /// set `color` and `style` of them
+    pub fn parse(text: &str, background: Background) -> Result<Self> {
+        let entries = config::parse(text)?;
+        let mut theme = Self::for_background(background);
+        for entry in entries.iter().filter(|entry| entry.table.is_empty()) {
+            match (entry.key.as_str(), &entry.value) {
+                ("base", Scalar::String(base)) => {
+                    theme = Self::builtin(base)
+                        .with_context(|| format!("line {}: no theme {base:?}", entry.line))?;
+                }
+                _ => bail!("line {}: unknown key {:?}", entry.line, entry.key),
+            }
+        }
+        for entry in entries.iter().filter(|entry| !entry.table.is_empty()) {
+            let Some(role) = Role::from_name(&entry.table) else {
+                bail!("line {}: unknown role {:?}", entry.line, entry.table);
+            };
+            let look = &mut theme.looks[role as usize];
+            let value = entry
+                .value
+                .as_str()
+                .with_context(|| format!("line {}: expected string", entry.line))?;
+            match entry.key.as_str() {
+                "color" => {
+                    look.paint = parse_paint(value)
+                        .with_context(|| format!("line {}: bad color {value:?}", entry.line))?;
+                }
+                "style" => {
+                    look.style = parse_style(value)
+                        .with_context(|| format!("line {}: bad style {value:?}", entry.line))?;
+                }
+                key => bail!("line {}: unknown key {key:?}", entry.line),
+            }
+        }
+        Ok(theme)
+    }
+
+    /// `PS1_THEME` is either a built-in theme name or a path to theme file. Without it, theme
+    /// matches the background
+    pub fn load(name: Option<&str>, background: Background) -> Result<Self> {
+        let Some(name) = name.filter(|name| !name.is_empty()) else {
+            return Ok(Self::for_background(background));
+        };
+        if let Some(theme) = Self::builtin(name) {
+            return Ok(theme);
+        }
+        let path = Path::new(name);
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("can not read theme {}", path.display()))?;
+        Self::parse(&text, background).with_context(|| format!("in theme {}", path.display()))
+    }
+
+    /// Color and style of `what` shown as `role`
+    pub fn paint(&self, role: Role, what: &str) -> (Color, Style) {
+        let look = self.looks[role as usize];
+        let color = match look.paint {
+            Paint::Fixed(color) => color,
+            Paint::Hashed => palette::color_of(what, self.background),
+        };
+        (color, look.style)
+    }
+}
+
+/// Color as in `Color::parse`, or `hash`
+fn parse_paint(value: &str) -> Option<Paint> {
+    if value == "hash" {
+        Some(Paint::Hashed)
+    } else {
+        Color::parse(value).map(Paint::Fixed)
+    }
+}
+
+/// Comma-separated `bold` and `italic`, or empty
+fn parse_style(value: &str) -> Option<Style> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|flag| !flag.is_empty())
+        .try_fold(Style::empty(), |style, flag| match flag {
+            "bold" => Some(style | Style::BOLD),
+            "italic" => Some(style | Style::ITALIC),
+            _ => None,
+        })
+}
+
+thread_local! {
+    static THEME: RefCell<Theme> = RefCell::new(Theme::default());
+}
+
+/// Sets theme used to render on this thread
+pub fn set(theme: Theme) {
+    THEME.set(theme);
+}
+
+/// Color of the name, as of hostnames and usernames
+pub fn hashed(what: &str) -> Color {
+    THEME.with_borrow(|theme| palette::color_of(what, theme.background))
+}
+
+pub fn paint(role: Role, what: &str) -> (Color, Style) {
+    THEME.with_borrow(|theme| theme.paint(role, what))
+}
@@ -0,0 +1,109 @@
+//! Terminal title, made from `PS1_TITLE` template
+
+use crate::{Environment, file};
+
+/// Template used when `PS1_TITLE` is not set
+const DEFAULT: &str = "{user}@{host}: {cwd}";
+
+/// Title with `command` running, or `None` if `PS1_TITLE` is set empty to leave title to the
+/// terminal. Unknown placeholders are kept as they are
+pub fn make(environ: &Environment, command: &str) -> Option<String> {
+    let template = environ.var("PS1_TITLE").unwrap_or(DEFAULT);
+    if template.is_empty() {
+        return None;
+    }
+    let mut res = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        res.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find('}') else {
+            break;
+        };
+        match placeholder(environ, command, &rest[1..end]) {
+            Some(value) => res.push_str(&value),
+            None => res.push_str(&rest[..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    res.push_str(rest);
+    // Control characters would end the escape sequence which sets title
+    Some(res.replace(char::is_control, " "))
+}
+
+fn placeholder(environ: &Environment, command: &str, name: &str) -> Option<String> {
+    let tree = environ.git_tree.as_deref();
+    Some(match name {
+        "user" => environ.user.clone(),
+        "host" => environ.host.clone(),
+        "cwd" => cwd(environ),
+        "repo" => tree
+            .and_then(|tree| tree.file_name())
+            .map(|dir| dir.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        "git_branch" => tree.and_then(file::git_branch).unwrap_or_default(),
+        "cmd" => command.trim().to_owned(),
+        _ => return None,
+    })
+}
+
+/// Working directory with homes shown as `~user`
+fn cwd(environ: &Environment) -> String {
+    let Some((home, user)) = &environ.current_home else {
+        return environ.work_dir.display().to_string();
+    };
+    let wd = environ
+        .work_dir
+        .strip_prefix(home)
+        .unwrap_or(&environ.work_dir);
+    if wd.as_os_str().is_empty() {
+        format!("~{user}")
+    } else {
+        format!("~{user}/{}", wd.display())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::make;
+    use crate::Environment;
+    use std::path::PathBuf;
+
+    #[test]
+    fn fills_template() {
+        let mut environ = Environment::at(PathBuf::from("/"), PathBuf::from("/"));
+        environ.user = "alice".to_owned();
+        environ.host = "box".to_owned();
+        environ.work_dir = PathBuf::from("/home/alice/src");
+        environ.git_tree = None;
+        environ.current_home = Some((PathBuf::from("/home/alice"), "alice".to_owned()));
+        for (template, command, expected) in [
+            (None, "", Some("alice@box: ~alice/src")),
+            (Some(""), "ls", None),
+            (
+                Some("{cmd} in {cwd}"),
+                " ls -l ",
+                Some("ls -l in ~alice/src"),
+            ),
+            (Some("[{repo}{git_branch}]"), "", Some("[]")),
+            // Unknown placeholders and unterminated braces are kept
+            (Some("{user} {nope} {}"), "", Some("alice {nope} {}")),
+            (Some("{host}: {user"), "", Some("box: {user")),
+            (Some("{{user}}"), "", Some("{{user}}")),
+            // Control characters would end the title
+            (Some("{cmd}"), "make\tall\n", Some("make all")),
+            (
+                Some("{cmd}"),
+                "printf '\x1b]0;x\x07'",
+                Some("printf ' ]0;x '"),
+            ),
+            (Some("a\x1bb"), "", Some("a b")),
+        ] {
+            environ.vars.clear();
+            if let Some(template) = template {
+                environ.vars.insert("PS1_TITLE".into(), template.into());
+            }
+            assert_eq!(make(&environ, command).as_deref(), expected, "{template:?}");
+        }
+    }
+}
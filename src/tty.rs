@@ -0,0 +1,121 @@
+//! Questions to the terminal itself, answered on the controlling tty
+
+use rustix::{
+    fs::{Mode, OFlags},
+    termios::{OptionalActions, SpecialCodeIndex},
+};
+use std::{
+    io::{Read as _, Write as _},
+    os::fd::OwnedFd,
+    time::{Duration, Instant},
+};
+
+/// Asks terminal for its background color with OSC 11, waiting for the answer at most `timeout`
+pub fn query_background(timeout: Duration) -> Option<(u8, u8, u8)> {
+    // Answer ends with either BEL or ST
+    let answer = query(b"\x1b]11;?\x07", timeout, |answer| {
+        answer.ends_with(b"\x07") || answer.ends_with(b"\x1b\\")
+    })?;
+    parse_color(&answer)
+}
+
+/// Prints `glyph` at the start of the line and asks with DSR where the cursor is after it, which
+/// tells how many columns the font takes for it. The line is cleared afterwards
+pub fn probe_width(glyph: char, timeout: Duration) -> Option<usize> {
+    let request = format!("\r{glyph}\x1b[6n\r\x1b[K");
+    let answer = query(request.as_bytes(), timeout, |answer| answer.ends_with(b"R"))?;
+    let (_, column) = parse_position(&answer)?;
+    column.checked_sub(1)
+}
+
+/// Writes `request` to the terminal in raw mode and reads the answer until it is `complete`
+fn query(request: &[u8], timeout: Duration, complete: impl Fn(&[u8]) -> bool) -> Option<String> {
+    let tty = rustix::fs::open("/dev/tty", OFlags::RDWR | OFlags::NOCTTY, Mode::empty()).ok()?;
+    let saved = rustix::termios::tcgetattr(&tty).ok()?;
+
+    let mut raw = saved.clone();
+    raw.make_raw();
+    // Every read waits a tenth of a second at most, so that silent terminals are not waited for
+    raw.special_codes[SpecialCodeIndex::VMIN] = 0;
+    raw.special_codes[SpecialCodeIndex::VTIME] = 1;
+    rustix::termios::tcsetattr(&tty, OptionalActions::Now, &raw).ok()?;
+
+    let answer = ask(&tty, request, timeout, complete);
+
+    let _restored = rustix::termios::tcsetattr(&tty, OptionalActions::Now, &saved);
+    answer
+}
+
+fn ask(
+    tty: &OwnedFd,
+    request: &[u8],
+    timeout: Duration,
+    complete: impl Fn(&[u8]) -> bool,
+) -> Option<String> {
+    let mut file = std::fs::File::from(tty.try_clone().ok()?);
+    file.write_all(request).ok()?;
+
+    let start = Instant::now();
+    let mut answer = vec![];
+    let mut buf = [0; 64];
+    while start.elapsed() < timeout {
+        let len = file.read(&mut buf).ok()?;
+        answer.extend_from_slice(&buf[..len]);
+        if complete(&answer) {
+            return String::from_utf8(answer).ok();
+        }
+    }
+    None
+}
+
+/// Parses `ESC ] 11 ; rgb:RRRR/GGGG/BBBB` with one to four hex digits per channel
+fn parse_color(answer: &str) -> Option<(u8, u8, u8)> {
+    let start = answer.find("]11;rgb:")?;
+    let rgb = answer[start + 8..].trim_end_matches(['\x07', '\x1b', '\\']);
+    let mut channels = rgb.split('/').map(|channel| {
+        let len = u32::try_from(channel.len())
+            .ok()
+            .filter(|len| (1..=4).contains(len))?;
+        let value = u32::from_str_radix(channel, 16).ok()?;
+        u8::try_from(value * 255 / (16_u32.pow(len) - 1)).ok()
+    });
+    let color = (channels.next()??, channels.next()??, channels.next()??);
+    channels.next().is_none().then_some(color)
+}
+
+/// Parses `ESC [ row ; column R`, as answered to DSR 6
+fn parse_position(answer: &str) -> Option<(usize, usize)> {
+    let start = answer.rfind("\x1b[")?;
+    let (row, column) = answer[start + 2..].strip_suffix('R')?.split_once(';')?;
+    Some((row.parse().ok()?, column.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_color, parse_position};
+
+    #[test]
+    fn parses_answers() {
+        for (answer, color) in [
+            ("\x1b]11;rgb:ffff/ffff/ffff\x1b\\", Some((255, 255, 255))),
+            ("\x1b]11;rgb:1e1e/1e1e/2e2e\x07", Some((30, 30, 46))),
+            ("\x1b]11;rgb:f/8/0\x07", Some((255, 136, 0))),
+            ("\x1b]11;rgb:ff/ff\x07", None),
+            ("\x1b]11;rgba:ffff/ffff/ffff/ffff\x07", None),
+        ] {
+            assert_eq!(parse_color(answer), color, "{answer:?}");
+        }
+    }
+
+    #[test]
+    fn parses_positions() {
+        for (answer, position) in [
+            ("\x1b[12;3R", Some((12, 3))),
+            ("\x1b[1;1R", Some((1, 1))),
+            ("\x1b[12R", None),
+            ("\x1b[12;xR", None),
+        ] {
+            assert_eq!(parse_position(answer), position, "{answer:?}");
+        }
+    }
+}
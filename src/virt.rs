@@ -1,4 +1,4 @@
-use crate::file;
+use crate::{file, sysroot::Sysroot};
 use anyhow::Result;
 use std::{
     fs::File,
@@ -6,93 +6,233 @@ use std::{
     path::Path,
 };
 
-#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
-fn detect_vm_cpuid() -> Option<bool> {
-    let cpuid = raw_cpuid::CpuId::new();
-    let vendor_info = cpuid.get_vendor_info()?;
-    match vendor_info.as_str() {
-        // Known to only belong to hypervisors
-        "XenVMMXenVMM" | "KVMKVMKVM" | "Linux KVM Hv" | "TCGTCGTCGTCG" | "VMwareVMware"
-        | "Microsoft Hv" | "bhyve bhyve " | "QNXQVMBSQG" | "ACRNACRNACRN" | "SRESRESRESRE"
-        | "MicrosoftXTA" | "VirtualApple" | "PowerVM Lx86" | "Neko Project" => Some(true),
-
-        // Known to only belong to hardware
-        "GenuineIntel" | "AuthenticAMD" | "CentaurHauls" | "CyrixInstead" | "GenuineIotel"
-        | "TransmetaCPU" | "GenuineTMx86" | "Geode by NSC" | "NexGenDriven" | "RiseRiseRise"
-        | "SiS SiS SiS " | "UMC UMC UMC " | "Vortex86 SoC" | "  Shanghai  " | "HygonGenuine"
-        | "Genuine  RDC" | "E2K MACHINE" | "VIA VIA VIA " | "AMD ISBETTER" => Some(false),
-
-        other => {
-            eprintln!("CPUID returned unknown vendor info: {other:?}");
-            None
+/// Virtualization technology, named as in `systemd-detect-virt`
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum Virtualization {
+    Kvm,
+    /// QEMU without hardware acceleration, TCG
+    Qemu,
+    Bochs,
+    Xen,
+    /// Xen control domain, which is the host and not a virtual machine
+    XenDom0,
+    Uml,
+    Vmware,
+    /// Oracle `VirtualBox`
+    Oracle,
+    /// Hyper-V
+    Microsoft,
+    Zvm,
+    Parallels,
+    Bhyve,
+    Qnx,
+    Acrn,
+    PowerVm,
+    Apple,
+    Sre,
+    Amazon,
+    /// Virtual machine of unknown kind
+    VmOther,
+
+    Docker,
+    Podman,
+    Lxc,
+    LxcLibvirt,
+    SystemdNspawn,
+    Rkt,
+    Wsl,
+    Proot,
+    OpenVz,
+    Flatpak,
+    /// Container of unknown kind
+    ContainerOther,
+}
+
+impl Virtualization {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Kvm => "kvm",
+            Self::Qemu => "qemu",
+            Self::Bochs => "bochs",
+            Self::Xen => "xen",
+            Self::XenDom0 => "xen-dom0",
+            Self::Uml => "uml",
+            Self::Vmware => "vmware",
+            Self::Oracle => "oracle",
+            Self::Microsoft => "microsoft",
+            Self::Zvm => "zvm",
+            Self::Parallels => "parallels",
+            Self::Bhyve => "bhyve",
+            Self::Qnx => "qnx",
+            Self::Acrn => "acrn",
+            Self::PowerVm => "powervm",
+            Self::Apple => "apple",
+            Self::Sre => "sre",
+            Self::Amazon => "amazon",
+            Self::VmOther => "vm-other",
+            Self::Docker => "docker",
+            Self::Podman => "podman",
+            Self::Lxc => "lxc",
+            Self::LxcLibvirt => "lxc-libvirt",
+            Self::SystemdNspawn => "systemd-nspawn",
+            Self::Rkt => "rkt",
+            Self::Wsl => "wsl",
+            Self::Proot => "proot",
+            Self::OpenVz => "openvz",
+            Self::Flatpak => "flatpak",
+            Self::ContainerOther => "container-other",
         }
     }
+
+    /// Technology as `systemd-detect-virt` reports it, which takes Xen dom0 for bare metal
+    pub fn reported(self) -> Option<Self> {
+        (self != Self::XenDom0).then_some(self)
+    }
+
+    /// Whether this is a virtual machine guest, as opposed to host or container
+    pub fn is_vm(self) -> bool {
+        !self.is_container() && self != Self::XenDom0
+    }
+
+    pub fn is_container(self) -> bool {
+        matches!(
+            self,
+            Self::Docker
+                | Self::Podman
+                | Self::Lxc
+                | Self::LxcLibvirt
+                | Self::SystemdNspawn
+                | Self::Rkt
+                | Self::Wsl
+                | Self::Proot
+                | Self::OpenVz
+                | Self::Flatpak
+                | Self::ContainerOther
+        )
+    }
+
+    /// Parses container manager name as in `container=` variable of init process
+    fn from_container_name(name: &str) -> Self {
+        match name.trim() {
+            "docker" => Self::Docker,
+            "podman" => Self::Podman,
+            "lxc" => Self::Lxc,
+            "lxc-libvirt" => Self::LxcLibvirt,
+            "systemd-nspawn" => Self::SystemdNspawn,
+            "rkt" => Self::Rkt,
+            "wsl" => Self::Wsl,
+            "proot" => Self::Proot,
+            "flatpak" => Self::Flatpak,
+            _ => Self::ContainerOther,
+        }
+    }
+}
+
+/// Outcome of a single detection method
+enum Verdict {
+    /// Known to be real hardware, no need to look further
+    Metal,
+    Virtual(Virtualization),
+    /// This method can not tell
+    Unknown,
 }
 
-#[cfg(not(any(target_arch = "x86_64", target_arch = "x86")))]
-fn detect_vm_cpuid() -> Option<bool> {
-    None
+/// Only hypervisors set the hypervisor bit, and they name themselves in the hypervisor leaf. CPU
+/// vendor is of no help, as guests see the physical one
+fn detect_vm_cpuid(root: &Sysroot) -> Verdict {
+    use Virtualization::*;
+    let Some(cpuid) = root.cpuid() else {
+        return Verdict::Unknown;
+    };
+    let Some(signature) = &cpuid.hypervisor else {
+        return Verdict::Metal;
+    };
+    Verdict::Virtual(match signature.as_str() {
+        "XenVMMXenVMM" => Xen,
+        "KVMKVMKVM" | "Linux KVM Hv" => Kvm,
+        "TCGTCGTCGTCG" => Qemu,
+        "VMwareVMware" => Vmware,
+        "Microsoft Hv" => Microsoft,
+        "bhyve bhyve " => Bhyve,
+        "QNXQVMBSQG" => Qnx,
+        "ACRNACRNACRN" => Acrn,
+        "SRESRESRESRE" => Sre,
+        "VirtualApple" => Apple,
+        "PowerVM Lx86" => PowerVm,
+        _ => VmOther,
+    })
 }
 
-fn detect_vm_device_tree() -> Result<Option<bool>> {
-    if std::fs::exists("/proc/device-tree/hypervisor/compatible")? {
-        return Ok(Some(true));
+fn detect_vm_device_tree(root: &Sysroot) -> Result<Verdict> {
+    match std::fs::read_to_string(root.path("/proc/device-tree/hypervisor/compatible")) {
+        Ok(s) if s.starts_with("linux,kvm") => return Ok(Verdict::Virtual(Virtualization::Kvm)),
+        Ok(s) if s.contains("xen") => return Ok(Verdict::Virtual(Virtualization::Xen)),
+        Ok(s) if s.contains("vmware") => return Ok(Verdict::Virtual(Virtualization::Vmware)),
+        Ok(_) => return Ok(Verdict::Virtual(Virtualization::VmOther)),
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
     }
 
-    if std::fs::exists("/proc/device-tree/ibm,partition-name")?
-        && std::fs::exists("/proc/device-tree/hmc-managed?")?
-        && !std::fs::exists("/proc/device-tree/chosen/qemu,graphic-width")?
+    if std::fs::exists(root.path("/proc/device-tree/ibm,partition-name"))?
+        && std::fs::exists(root.path("/proc/device-tree/hmc-managed?"))?
+        && !std::fs::exists(root.path("/proc/device-tree/chosen/qemu,graphic-width"))?
     {
-        return Ok(Some(true));
+        return Ok(Verdict::Virtual(Virtualization::PowerVm));
     }
 
-    match file::exists_that("/proc/device-tree", |name| name.contains("fw-cfg")) {
-        Ok(true) => return Ok(Some(true)),
+    match file::exists_that(root.path("/proc/device-tree"), |name| {
+        name.contains("fw-cfg")
+    }) {
+        Ok(true) => return Ok(Verdict::Virtual(Virtualization::Qemu)),
         Ok(false) => {}
-        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Verdict::Unknown),
         Err(e) => return Err(e.into()),
     }
 
-    match std::fs::read_to_string("/proc/device-tree/compatible") {
-        Ok(s) if s == "qemu,pseries" => Ok(Some(true)),
-        Ok(_) => Ok(Some(false)),
-        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+    match std::fs::read_to_string(root.path("/proc/device-tree/compatible")) {
+        Ok(s) if s.trim_end_matches('\0') == "qemu,pseries" => {
+            Ok(Verdict::Virtual(Virtualization::Qemu))
+        }
+        Ok(_) => Ok(Verdict::Metal),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Verdict::Unknown),
         Err(e) => Err(e.into()),
     }
 }
 
-fn detect_vm_dmi_vendor_path(path: &Path) -> Result<bool> {
+fn detect_vm_dmi_vendor_path(path: &Path) -> Result<Option<Virtualization>> {
+    use Virtualization::*;
     let name = match std::fs::read_to_string(path) {
         Ok(s) => s,
-        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
         Err(e) => return Err(e.into()),
     };
 
-    for vendor in [
-        "KVM",
-        "OpenStack",
-        "KubeVirt",
-        "Amazon EC2",
-        "QEMU",
-        "VMware",
-        "VMW",
-        "innotek GmbH",
-        "VirtualBox",
-        "Xen",
-        "Bochs",
-        "Parallels",
-        "BHYVE",
-        "Hyper-V",
-        "Apple Virtualization",
+    for (vendor, virt) in [
+        ("KVM", Kvm),
+        ("OpenStack", Kvm),
+        ("KubeVirt", Kvm),
+        ("Amazon EC2", Amazon),
+        ("QEMU", Qemu),
+        ("VMware", Vmware),
+        ("VMW", Vmware),
+        ("innotek GmbH", Oracle),
+        ("VirtualBox", Oracle),
+        ("Xen", Xen),
+        ("Bochs", Bochs),
+        ("Parallels", Parallels),
+        ("BHYVE", Bhyve),
+        ("Hyper-V", Microsoft),
+        ("Apple Virtualization", Apple),
     ] {
         if name.starts_with(vendor) {
-            return Ok(true);
+            return Ok(Some(virt));
         }
     }
-    Ok(false)
+    Ok(None)
 }
 
-fn detect_vm_dmi_vendor() -> Result<bool> {
+fn detect_vm_dmi_vendor(root: &Sysroot) -> Result<Option<Virtualization>> {
     for path in [
         "/sys/class/dmi/id/product_name",
         "/sys/class/dmi/id/sys_vendor",
@@ -100,108 +240,209 @@ fn detect_vm_dmi_vendor() -> Result<bool> {
         "/sys/class/dmi/id/bios_vendor",
         "/sys/class/dmi/id/product_version",
     ] {
-        if detect_vm_dmi_vendor_path(path.as_ref())? {
-            return Ok(true);
+        if let Some(virt) = detect_vm_dmi_vendor_path(&root.path(path))? {
+            return Ok(Some(virt));
         }
     }
-    Ok(false)
+    Ok(None)
 }
 
-fn detect_vm_smbios() -> Result<bool> {
+fn detect_vm_smbios(root: &Sysroot) -> Result<bool> {
     // See 7.1.2.2 "BIOS Characteristics Extension Byte 2" at [SMBIOS spec]
     // [SMBIOS spec]: https://www.dmtf.org/sites/default/files/standards/documents/DSP0134_3.4.0.pdf
-    Ok(std::fs::read("/sys/firmware/dmi/entries/0-0/raw")?
-        .get(0x13)
-        .is_some_and(|x| ((x >> 4_i32) & 1) == 1))
+    match std::fs::read(root.path("/sys/firmware/dmi/entries/0-0/raw")) {
+        Ok(raw) => Ok(raw.get(0x13).is_some_and(|x| ((x >> 4_i32) & 1) == 1)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
 }
 
-fn detect_vm_dmi_metal() -> Result<bool> {
-    let name = std::fs::read_to_string("/sys/class/dmi/id/product_name")?;
-    Ok(name.contains(".metal-") || name.ends_with(".metal"))
+/// Bare-metal AWS instances still report Amazon EC2 as their vendor
+fn detect_vm_dmi_metal(root: &Sysroot) -> Result<bool> {
+    match std::fs::read_to_string(root.path("/sys/class/dmi/id/product_name")) {
+        Ok(name) => {
+            let name = name.trim_end();
+            Ok(name.contains(".metal-") || name.ends_with(".metal"))
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
 }
 
-fn detect_vm_xen_dom0() -> Result<bool> {
-    match std::fs::read_to_string("/sys/hypervisor/properties/features") {
-        Ok(s) => return Ok((u64::from_str_radix(&s, 16)? >> 11) & 1 == 1),
+fn detect_vm_xen_dom0(root: &Sysroot) -> Result<bool> {
+    match std::fs::read_to_string(root.path("/sys/hypervisor/properties/features")) {
+        Ok(s) => return Ok((u64::from_str_radix(s.trim(), 16)? >> 11) & 1 == 1),
         Err(e) if e.kind() == ErrorKind::NotFound => {}
         Err(e) => return Err(e.into()),
     }
 
-    match std::fs::read_to_string("/proc/xen/capabilities") {
+    match std::fs::read_to_string(root.path("/proc/xen/capabilities")) {
         Ok(s) => Ok(s.contains("control_d")),
         Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
         Err(e) => Err(e.into()),
     }
 }
 
-pub fn detect_vm() -> Result<bool> {
-    if let Ok(file) = File::open("/proc/cpuinfo")
+fn detect_vm_zvm(root: &Sysroot) -> Result<Option<Virtualization>> {
+    let file = match File::open(root.path("/proc/sysinfo")) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    // LPARs have sysinfo too, but hypervisor is only mentioned in guests
+    Ok(BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .find_map(|line| {
+            let control_program = line.strip_prefix("VM00 Control Program:")?;
+            Some(if control_program.contains("z/VM") {
+                Virtualization::Zvm
+            } else {
+                Virtualization::Kvm
+            })
+        }))
+}
+
+/// Virtual machine named by DMI, with bare-metal AWS instances and unnamed virtual machines told by
+/// SMBIOS
+fn detect_vm_dmi(root: &Sysroot) -> Result<Option<Virtualization>> {
+    match detect_vm_dmi_vendor(root)? {
+        Some(Virtualization::Amazon) if detect_vm_dmi_metal(root)? => Ok(None),
+        Some(virt) => Ok(Some(virt)),
+        None if detect_vm_smbios(root)? => Ok(Some(Virtualization::VmOther)),
+        None => Ok(None),
+    }
+}
+
+/// Detects virtual machine technology, with Xen dom0 reported as [`Virtualization::XenDom0`]
+///
+/// Order follows `systemd-detect-virt`: hypervisors which run on top of KVM or pretend to be
+/// Hyper-V are named by DMI first, then the hypervisor leaf of CPUID is trusted over the rest
+pub fn detect_vm(root: &Sysroot) -> Result<Option<Virtualization>> {
+    if let Ok(file) = File::open(root.path("/proc/cpuinfo"))
         && BufReader::new(file)
             .lines()
             .map_while(Result::ok)
             .any(|line| line.starts_with("vendor_id\t: User Mode Linux"))
     {
-        return Ok(true);
+        return Ok(Some(Virtualization::Uml));
     }
 
-    if let Some(res) = detect_vm_cpuid() {
-        return Ok(res);
+    let dmi = detect_vm_dmi(root)?;
+    if let Some(
+        virt @ (Virtualization::Oracle
+        | Virtualization::Xen
+        | Virtualization::Amazon
+        | Virtualization::Parallels),
+    ) = dmi
+    {
+        return Ok(Some(virt));
     }
 
-    if std::fs::exists("/proc/xen")? && !detect_vm_xen_dom0()?
-        || std::fs::exists("/sys/hypervisor/type")?
-        || std::fs::exists("/proc/sysinfo")?
-        || detect_vm_smbios()?
-        || detect_vm_dmi_vendor()?
-        || detect_vm_dmi_metal()?
-    {
-        return Ok(true);
+    if std::fs::exists(root.path("/proc/xen"))? {
+        return Ok(Some(if detect_vm_xen_dom0(root)? {
+            Virtualization::XenDom0
+        } else {
+            Virtualization::Xen
+        }));
     }
 
-    if let Some(res) = detect_vm_device_tree()? {
-        return Ok(res);
+    // Virtual machine of unknown kind, unless something else can name it
+    let mut other = false;
+    match detect_vm_cpuid(root) {
+        Verdict::Metal => return Ok(None),
+        Verdict::Virtual(Virtualization::VmOther) => other = true,
+        Verdict::Virtual(virt) => return Ok(Some(virt)),
+        Verdict::Unknown => {}
     }
 
-    Ok(false)
+    match dmi {
+        Some(Virtualization::VmOther) => other = true,
+        Some(virt) => return Ok(Some(virt)),
+        None => {}
+    }
+
+    match std::fs::read_to_string(root.path("/sys/hypervisor/type")) {
+        Ok(s) if s.trim() == "xen" => return Ok(Some(Virtualization::Xen)),
+        Ok(_) => other = true,
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    if let Some(virt) = detect_vm_zvm(root)? {
+        return Ok(Some(virt));
+    }
+
+    Ok(match detect_vm_device_tree(root)? {
+        Verdict::Virtual(virt) => Some(virt),
+        Verdict::Metal | Verdict::Unknown => other.then_some(Virtualization::VmOther),
+    })
 }
 
-pub fn detect_container() -> Result<bool> {
-    if std::fs::exists("/proc/vz")? && !std::fs::exists("/proc/bc")?
-        || std::fs::exists("/run/host/container-daemon")?
-        || std::fs::exists("/run/systemd/container")?
-        || std::fs::exists("/run/.containerenv")?
-        || std::fs::exists("/.dockerenv")?
-    {
-        return Ok(true);
+fn read_container_name(path: &Path) -> Result<Option<Virtualization>> {
+    match std::fs::read_to_string(path) {
+        Ok(name) => Ok(Some(Virtualization::from_container_name(&name))),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
     }
+}
 
-    if let Ok(s) = std::fs::read_to_string("/proc/sys/kernel/osrelease")
+/// Finds `container=` variable in environment of init process
+pub fn init_container_var(root: &Sysroot) -> Option<String> {
+    BufReader::new(File::open(root.path("/proc/1/environ")).ok()?)
+        .split(0)
+        .map_while(Result::ok)
+        .find_map(|var| String::from_utf8(var.strip_prefix(b"container=")?.to_vec()).ok())
+}
+
+pub fn detect_container(root: &Sysroot) -> Result<Option<Virtualization>> {
+    if std::fs::exists(root.path("/proc/vz"))? && !std::fs::exists(root.path("/proc/bc"))? {
+        return Ok(Some(Virtualization::OpenVz));
+    }
+
+    for path in ["/run/host/container-manager", "/run/systemd/container"] {
+        if let Some(virt) = read_container_name(&root.path(path))? {
+            return Ok(Some(virt));
+        }
+    }
+
+    for (path, virt) in [
+        ("/run/host/container-daemon", Virtualization::ContainerOther),
+        ("/run/.containerenv", Virtualization::Podman),
+        ("/.dockerenv", Virtualization::Docker),
+        ("/.flatpak-info", Virtualization::Flatpak),
+    ] {
+        if std::fs::exists(root.path(path))? {
+            return Ok(Some(virt));
+        }
+    }
+
+    if let Ok(s) = std::fs::read_to_string(root.path("/proc/sys/kernel/osrelease"))
         && (s.contains("Microsoft") || s.contains("WSL"))
     {
-        return Ok(true);
+        return Ok(Some(Virtualization::Wsl));
     }
 
-    if let Ok(file) = File::open("/proc/self/status")
+    if let Ok(file) = File::open(root.path("/proc/self/status"))
         && let Some(pid) = BufReader::new(file).lines().find_map(|line| {
             line.ok()?
                 .strip_prefix("TracerPid:\t")?
                 .parse::<usize>()
                 .ok()
         })
-        && let Ok(s) = std::fs::read_to_string(format!("/proc/{pid}/comm"))
+        && let Ok(s) = std::fs::read_to_string(root.path(format!("/proc/{pid}/comm")))
         && s.starts_with("proot")
     {
-        return Ok(true);
+        return Ok(Some(Virtualization::Proot));
     }
 
-    if let Ok(file) = File::open("/proc/1/environ")
-        && BufReader::new(file)
-            .split(0)
-            .map_while(Result::ok)
-            .any(|line| line.starts_with(b"container="))
-    {
-        return Ok(true);
-    }
+    Ok(init_container_var(root).map(|name| Virtualization::from_container_name(&name)))
+}
 
-    Ok(false)
+/// Detects container, and virtual machine if not in container
+pub fn detect(root: &Sysroot) -> Result<Option<Virtualization>> {
+    Ok(match detect_container(root)? {
+        Some(virt) => Some(virt),
+        None => detect_vm(root)?,
+    })
 }
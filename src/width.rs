@@ -0,0 +1,101 @@
+//! Width of rendered lines in terminal columns, which right alignment and fitting rely on
+
+use std::cell::Cell;
+use unicode_width::UnicodeWidthChar as _;
+
+/// Nerd font glyphs, which are in private use areas and take as many columns as the font and the
+/// terminal make them: first, last and width in double width fonts
+const NERD: &[(char, char, usize)] = &[
+    // Powerline separators are drawn to fill exactly one cell
+    ('\u{e0a0}', '\u{e0d7}', 1),
+    ('\u{e000}', '\u{f8ff}', 2),
+    ('\u{f0000}', '\u{ffffd}', 2),
+];
+
+/// Glyph which the terminal is asked to show to tell how wide nerd font glyphs are
+pub const PROBE: char = '\u{f07b}';
+
+thread_local! {
+    static AMBIGUOUS_WIDE: Cell<bool> = const { Cell::new(false) };
+    static NERD_WIDE: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Whether East Asian ambiguous characters like `…` take two columns, as terminals show them in
+/// CJK locales. `PS1_AMBIGUOUS_WIDTH=1` or `2` overrides the guess from locale
+pub fn ambiguous_wide(var: impl Fn(&str) -> Option<String>) -> bool {
+    match var("PS1_AMBIGUOUS_WIDTH").as_deref() {
+        Some("2") => true,
+        Some("1") => false,
+        _ => ["LC_ALL", "LC_CTYPE", "LANG"]
+            .into_iter()
+            .find_map(|name| var(name).filter(|locale| !locale.is_empty()))
+            .is_some_and(|locale| ["zh", "ja", "ko"].iter().any(|cjk| locale.starts_with(cjk))),
+    }
+}
+
+/// Sets width of ambiguous characters on this thread
+pub fn set_ambiguous_wide(wide: bool) {
+    AMBIGUOUS_WIDE.set(wide);
+}
+
+/// Whether nerd font glyphs take two columns, as `PS1_NERD_WIDTH=2` says. `env` script fills it by
+/// asking the terminal once per session, glyphs are taken as one column wide if it can not tell
+pub fn nerd_wide(var: impl Fn(&str) -> Option<String>) -> bool {
+    var("PS1_NERD_WIDTH").as_deref() == Some("2")
+}
+
+/// Sets width of nerd font glyphs on this thread
+pub fn set_nerd_wide(wide: bool) {
+    NERD_WIDE.set(wide);
+}
+
+fn char_width(c: char) -> usize {
+    if let Some(&(.., wide)) = NERD
+        .iter()
+        .find(|&&(first, last, _)| (first..=last).contains(&c))
+    {
+        // Private use characters are ambiguous too, but fonts decide their width on their own
+        return if NERD_WIDE.get() { wide } else { 1 };
+    }
+    if AMBIGUOUS_WIDE.get() {
+        c.width_cjk()
+    } else {
+        c.width()
+    }
+    .unwrap_or_default()
+}
+
+/// Columns taken by `s`, markup between `\x01` and `\x02` takes none
+pub fn of(s: &str) -> usize {
+    let mut res = 0;
+    let mut visible = true;
+    for c in s.chars() {
+        match c {
+            '\x01' => visible = false,
+            '\x02' => visible = true,
+            _ if visible => res += char_width(c),
+            _ => {}
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{of, set_ambiguous_wide, set_nerd_wide};
+
+    #[test]
+    fn counts_columns() {
+        let line = "\x01\x1b[31m\x02[\u{f07b} ~/src]\x01\x1b[0m\x02 \u{e0b0} …";
+        for (ambiguous_wide, nerd_wide, width) in [
+            (false, false, 13),
+            (false, true, 14),
+            (true, false, 14),
+            (true, true, 15),
+        ] {
+            set_ambiguous_wide(ambiguous_wide);
+            set_nerd_wide(nerd_wide);
+            assert_eq!(of(line), width, "{ambiguous_wide} {nerd_wide}");
+        }
+    }
+}
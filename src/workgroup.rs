@@ -2,31 +2,36 @@ use anyhow::{Context as _, Result, anyhow};
 use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD as base64engine};
 use orion::aead::{self, SecretKey};
 use std::{
-    env,
+    ffi::OsStr,
     fs::File,
     io::{BufRead as _, BufReader},
+    path::PathBuf,
 };
 
 pub struct WorkgroupKey(SecretKey);
 
 impl WorkgroupKey {
-    pub fn load() -> Result<Self> {
+    /// Key file in `.ssh` of `home`
+    pub fn path(home: Option<&OsStr>) -> PathBuf {
+        let mut path = home.unwrap_or_default().to_owned();
+        path.push("/.ssh/workgroup");
+        PathBuf::from(path)
+    }
+
+    pub fn load(home: Option<&OsStr>) -> Result<Self> {
         Ok(WorkgroupKey(SecretKey::from_slice(
             &base64engine.decode(
-                &BufReader::new(File::open(format!(
-                    "{}/.ssh/workgroup",
-                    env::var("HOME").unwrap_or_default()
-                ))?)
-                .lines()
-                .next()
-                .context("Workgroup key file is corrupted")??,
+                &BufReader::new(File::open(Self::path(home))?)
+                    .lines()
+                    .next()
+                    .context("Workgroup key file is corrupted")??,
             )?,
         )?))
     }
 
-    pub fn create() -> Result<()> {
+    pub fn create(home: Option<&OsStr>) -> Result<()> {
         Ok(std::fs::write(
-            format!("{}/.ssh/workgroup", env::var("HOME").unwrap_or_default()),
+            Self::path(home),
             base64engine.encode(SecretKey::default().unprotected_as_bytes()),
         )?)
     }
@@ -35,20 +40,25 @@ impl WorkgroupKey {
 pub struct SshChain(pub Vec<String>);
 
 impl SshChain {
-    fn open_impl(key: &WorkgroupKey) -> Result<Vec<String>> {
+    fn open_impl(key: &WorkgroupKey, chain: Option<&str>) -> Result<Vec<String>> {
         Ok(String::from_utf8(aead::open(
             &key.0,
-            &base64engine.decode(env::var("WORKGROUP_CHAIN")?)?,
+            &base64engine.decode(chain.context("No WORKGROUP_CHAIN")?)?,
         )?)?
         .split_whitespace()
         .map(ToOwned::to_owned)
         .collect::<Vec<_>>())
     }
 
-    pub fn open(key: Option<&WorkgroupKey>) -> SshChain {
+    /// Decodes `WORKGROUP_CHAIN`, falling back to client address from `SSH_CONNECTION`
+    pub fn open(
+        key: Option<&WorkgroupKey>,
+        chain: Option<&str>,
+        connection: Option<&str>,
+    ) -> SshChain {
         let ssh_chain = key
             .context("No workgroup key passed")
-            .and_then(Self::open_impl)
+            .and_then(|key| Self::open_impl(key, chain))
             .and_then(|chain| {
                 if chain.is_empty() {
                     Err(anyhow!("Empty ssh chain, but decoded"))
@@ -57,9 +67,9 @@ impl SshChain {
                 }
             });
 
-        SshChain(match (ssh_chain, env::var("SSH_CONNECTION")) {
-            (Err(_), Err(_)) => vec![],
-            (Err(_), Ok(conn)) => vec![conn.split_whitespace().next().unwrap_or("?").to_owned()],
+        SshChain(match (ssh_chain, connection) {
+            (Err(_), None) => vec![],
+            (Err(_), Some(conn)) => vec![conn.split_whitespace().next().unwrap_or("?").to_owned()],
             (Ok(ch), _) => ch,
         })
     }